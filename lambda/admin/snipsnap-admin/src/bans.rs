@@ -0,0 +1,32 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::error::Error;
+
+use chrono::{Duration, Utc};
+use snipsnap_lib::database::{Ban, BannedSubjectsTable, Database, Subject};
+
+/// The authorizer caches bans for this long, so changes take up to this long to apply. A ban also waits for policies
+/// API Gateway has cached to expire, since those allow the subject without asking the authorizer.
+const CACHE_SECONDS: i64 = 60;
+
+/// Ban `subject`, for `days` or forever.
+pub async fn ban(database: &Database, subject: Subject, reason: &str, days: Option<i64>) -> Result<bool, Box<dyn Error>> {
+    let ban = Ban::new(subject, reason, days.map(|days| Utc::now() + Duration::days(days)));
+    BannedSubjectsTable::ban(database, &ban).await?;
+    match ban.expires_at() {
+        Some(expires_at) => println!("Banned {} until {expires_at}", ban.subject()),
+        None => println!("Banned {}", ban.subject()),
+    }
+    println!("Warm authorizers may take up to {CACHE_SECONDS} seconds to notice, plus the API Gateway authorizer's \
+        result TTL on APIs that cache its policies");
+    Ok(true)
+}
+
+pub async fn unban(database: &Database, subject: Subject) -> Result<bool, Box<dyn Error>> {
+    BannedSubjectsTable::unban(database, &subject).await?;
+    println!("Unbanned {subject}");
+    println!("Warm authorizers may take up to {CACHE_SECONDS} seconds to notice");
+    Ok(true)
+}
@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use snipsnap_lib::database::{Database, DatabaseConfig, Subject};
+
+mod bans;
+mod openapi;
+mod tables;
+
+#[derive(Parser)]
+#[command(about = "Administration tool for the SnipSnap backend")]
+struct Cli {
+    /// Stage whose tables to manage, e.g. dev for dev-nonces. Defaults to SNIPSNAP_STAGE
+    #[arg(long, global = true)]
+    stage: Option<String>,
+    /// DynamoDB endpoint to use instead of the real service, e.g. http://localhost:8000 for DynamoDB Local
+    #[arg(long, global = true)]
+    endpoint: Option<String>,
+    #[arg(long, global = true)]
+    region: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create every table in the schema registry that does not exist yet
+    CreateTables,
+    /// Compare every deployed table against the schema registry and report drift
+    VerifyTables,
+    /// Print the OpenAPI document generated from the API types
+    Openapi {
+        /// Write the document here instead of to stdout, e.g. ../../../openapi.yaml
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Lock a user or device out of the API, even with valid tokens
+    Ban {
+        #[command(flatten)]
+        subject: SubjectArgs,
+        /// Why, shown in the authorizer's logs
+        #[arg(long)]
+        reason: String,
+        /// Lift the ban after this many days instead of never
+        #[arg(long)]
+        days: Option<i64>,
+    },
+    /// Lift a ban
+    Unban {
+        #[command(flatten)]
+        subject: SubjectArgs,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct SubjectArgs {
+    /// A user id, as sent in X-UserId
+    #[arg(long)]
+    user: Option<String>,
+    /// A device id, as sent in X-DeviceId
+    #[arg(long)]
+    device: Option<String>,
+}
+
+impl SubjectArgs {
+    fn subject(self) -> Subject {
+        match (self.user, self.device) {
+            (Some(user_id), _) => Subject::User(user_id),
+            // clap requires one of them
+            (None, device_id) => Subject::Device(device_id.unwrap_or_default()),
+        }
+    }
+}
+
+fn database(cli: &Cli) -> Result<Database, Box<dyn Error>> {
+    let mut config = DatabaseConfig::from_env();
+    if let Some(stage) = &cli.stage {
+        config = config.with_stage(stage);
+    }
+    if let Some(endpoint) = &cli.endpoint {
+        config = config.with_endpoint(endpoint);
+    }
+    if let Some(region) = &cli.region {
+        config = config.with_region(region);
+    }
+    Ok(Database::new(config)?)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let database = match database(&cli) {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match cli.command {
+        Command::CreateTables => tables::create_tables(&database).await,
+        Command::VerifyTables => tables::verify_tables(&database).await,
+        Command::Openapi { output } => openapi::openapi(output.as_deref()),
+        Command::Ban { subject, reason, days } => bans::ban(&database, subject.subject(), &reason, days).await,
+        Command::Unban { subject } => bans::unban(&database, subject.subject()).await,
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use snipsnap_lib::api::specification;
+
+/// Write the OpenAPI document generated from the API types to `output`, or stdout.
+pub fn openapi(output: Option<&Path>) -> Result<bool, Box<dyn Error>> {
+    let yaml = serde_yaml::to_string(&specification())?;
+    match output {
+        Some(output) => {
+            fs::write(output, yaml)?;
+            println!("Wrote {}", output.display());
+        },
+        None => print!("{yaml}"),
+    }
+    Ok(true)
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::error::Error;
+use std::time::Duration;
+
+use aws_sdk_dynamodb::error::DescribeTableErrorKind;
+use aws_sdk_dynamodb::model::{TableDescription, TableStatus, TimeToLiveSpecification, TimeToLiveStatus};
+use aws_sdk_dynamodb::types::SdkError;
+use snipsnap_lib::database::schema;
+use snipsnap_lib::database::Database;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 60;
+
+/// Create missing tables. Returns whether every table now matches its schema.
+pub async fn create_tables(database: &Database) -> Result<bool, Box<dyn Error>> {
+    let client = database.client();
+    for table in schema::tables() {
+        let table_name = database.table_name(table.name());
+        if describe(database, &table_name).await?.is_some() {
+            println!("{table_name}: exists");
+            continue;
+        }
+
+        client.create_table()
+            .table_name(&table_name)
+            .set_attribute_definitions(Some(table.attribute_definitions()))
+            .set_key_schema(Some(table.key_schema()))
+            .set_global_secondary_indexes(table.sdk_global_secondary_indexes())
+            .billing_mode(table.sdk_billing_mode())
+            .set_provisioned_throughput(table.provisioned_throughput())
+            .send()
+            .await?;
+        wait_until_active(database, &table_name).await?;
+
+        if let Some(ttl_attribute) = table.ttl_attribute() {
+            client.update_time_to_live()
+                .table_name(&table_name)
+                .time_to_live_specification(TimeToLiveSpecification::builder()
+                    .enabled(true)
+                    .attribute_name(ttl_attribute)
+                    .build())
+                .send()
+                .await?;
+        }
+        println!("{table_name}: created");
+    }
+
+    verify_tables(database).await
+}
+
+/// Report drift for every table. Returns whether every table matches its schema.
+pub async fn verify_tables(database: &Database) -> Result<bool, Box<dyn Error>> {
+    let mut all_match = true;
+    for table in schema::tables() {
+        let table_name = database.table_name(table.name());
+        match describe(database, &table_name).await? {
+            Some(description) => {
+                let ttl_attribute = ttl_attribute(database, &table_name).await?;
+                let drift = table.drift(&description, ttl_attribute.as_deref());
+                if drift.is_empty() {
+                    println!("{table_name}: ok");
+                } else {
+                    all_match = false;
+                    for d in drift {
+                        println!("{table_name}: {d}");
+                    }
+                }
+            }
+            None => {
+                all_match = false;
+                println!("{table_name}: missing");
+            }
+        }
+    }
+    Ok(all_match)
+}
+
+async fn describe(database: &Database, table_name: &str) -> Result<Option<TableDescription>, Box<dyn Error>> {
+    match database.client().describe_table().table_name(table_name).send().await {
+        Ok(output) => Ok(output.table().cloned()),
+        Err(SdkError::ServiceError { err, .. }) if matches!(err.kind, DescribeTableErrorKind::ResourceNotFoundException(_)) => Ok(None),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+async fn wait_until_active(database: &Database, table_name: &str) -> Result<(), Box<dyn Error>> {
+    for _ in 0..POLL_ATTEMPTS {
+        if let Some(description) = describe(database, table_name).await? {
+            if description.table_status() == Some(&TableStatus::Active) {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Err(format!("Timed out waiting for {table_name} to become active").into())
+}
+
+/// The attribute TTL is enabled (or being enabled) on, if any.
+async fn ttl_attribute(database: &Database, table_name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let output = database.client().describe_time_to_live().table_name(table_name).send().await?;
+    Ok(output.time_to_live_description()
+        .filter(|d| matches!(d.time_to_live_status(), Some(TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)))
+        .and_then(|d| d.attribute_name())
+        .map(String::from))
+}
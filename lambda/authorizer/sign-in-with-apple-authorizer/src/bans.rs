@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Banned users and devices, cached in the process so most requests don't wait on DynamoDB.
+//!
+//! Lookups are cached whether or not they found a ban, so a new ban takes up to the cache's TTL to reach a warm
+//! lambda, and so does lifting one.
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use snipsnap_lib::database::{Ban, BannedSubjectsTable, Database, Subject};
+use tracing::warn;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// Subjects to remember, least recently used first out.
+const MAX_CACHED: usize = 10_000;
+
+enum Source {
+    Database(Database),
+    /// For running locally.
+    Memory(Vec<Ban>),
+}
+
+pub struct Bans {
+    source: Source,
+    ttl: Duration,
+    cache: Mutex<LruCache<Subject, (Option<Ban>, Instant)>>,
+}
+
+impl Bans {
+
+    pub fn new(database: Database) -> Bans {
+        Bans::with_source(Source::Database(database))
+    }
+
+    /// Only `bans`, without a database.
+    pub fn memory(bans: Vec<Ban>) -> Bans {
+        Bans::with_source(Source::Memory(bans))
+    }
+
+    fn with_source(source: Source) -> Bans {
+        Bans {
+            source,
+            ttl: DEFAULT_TTL,
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_CACHED).unwrap_or(NonZeroUsize::MIN))),
+        }
+    }
+
+    /// The subject's active ban, if any.
+    ///
+    /// Failed lookups count as not banned, so DynamoDB having trouble doesn't lock everyone out, and aren't cached.
+    pub async fn find(&self, subject: &Subject) -> Option<Ban> {
+        if let Some(cached) = self.cached(subject) {
+            return cached.filter(Ban::is_active);
+        }
+
+        let ban = match &self.source {
+            Source::Database(database) => match BannedSubjectsTable::get_ban(database, subject).await {
+                Ok(ban) => ban,
+                Err(e) => {
+                    warn!(subject = %subject, diagnostic = %e, "Failed to look up ban");
+                    return None;
+                }
+            },
+            Source::Memory(bans) => bans.iter().find(|ban| ban.subject() == subject && ban.is_active()).cloned(),
+        };
+        self.cache(subject, ban.clone());
+        ban
+    }
+
+    fn cached(&self, subject: &Subject) -> Option<Option<Ban>> {
+        let mut cache = self.cache.lock().ok()?;
+        match cache.get(subject) {
+            Some((_, cached_at)) if cached_at.elapsed() >= self.ttl => {
+                cache.pop(subject);
+                None
+            }
+            Some((ban, _)) => Some(ban.clone()),
+            None => None,
+        }
+    }
+
+    fn cache(&self, subject: &Subject, ban: Option<Ban>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.put(subject.clone(), (ban, Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::Mutex;
+
+    use lru::LruCache;
+    use snipsnap_lib::database::{Ban, Subject};
+
+    use crate::bans::Bans;
+
+    #[tokio::test]
+    async fn test_cache_bounded() {
+        let banned = Subject::User(String::from("banned"));
+        let mut bans = Bans::memory(vec![Ban::new(banned.clone(), "spam", None)]);
+        bans.cache = Mutex::new(LruCache::new(NonZeroUsize::new(2).expect("Zero capacity")));
+
+        assert!(bans.find(&banned).await.is_some());
+        for user_id in ["a", "b", "c"] {
+            assert!(bans.find(&Subject::User(String::from(user_id))).await.is_none());
+        }
+        let cache = bans.cache.lock().expect("Poisoned cache");
+        assert_eq!(cache.len(), 2, "cache grew past its capacity");
+        assert!(!cache.contains(&banned), "kept the least recently used subject");
+    }
+}
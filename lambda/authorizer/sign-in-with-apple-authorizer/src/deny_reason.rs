@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::fmt::{Display, Formatter};
+
+use jsonwebtoken::errors::ErrorKind;
+use snipsnap_lib::http::session::SessionError;
+
+/// Why a request was denied, one for every way the handler can say no.
+///
+/// The [name](DenyReason::name) is logged and counted per reason, so alarms can watch e.g. nonce mismatches. The
+/// [message](DenyReason::message) is what the caller sees in `context.failure`, which is deliberately vaguer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DenyReason {
+    MissingAuthorizationHeader,
+    InvalidAuthorizationHeader,
+    MissingUserIdHeader,
+    MissingDeviceIdHeader,
+    // sign_in_with_apple::Error
+    TokenExpired,
+    InvalidToken,
+    HeaderAlgorithmUnspecified,
+    KeyIdNotFound,
+    KeyNotFound,
+    AppleKeysUnavailable,
+    IssuerMismatch,
+    ClientIdMismatch,
+    NonceMismatch,
+    // session tokens
+    SessionExpired,
+    InvalidSessionToken,
+    NotAdmin,
+    // banned subjects
+    BannedUser,
+    BannedDevice,
+    // a build older than the minimum that also failed authentication
+    UpgradeRequired,
+}
+
+impl DenyReason {
+
+    /// The value of the `reason` log field and metric dimension.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DenyReason::MissingAuthorizationHeader => "MissingAuthorizationHeader",
+            DenyReason::InvalidAuthorizationHeader => "InvalidAuthorizationHeader",
+            DenyReason::MissingUserIdHeader => "MissingUserIdHeader",
+            DenyReason::MissingDeviceIdHeader => "MissingDeviceIdHeader",
+            DenyReason::TokenExpired => "TokenExpired",
+            DenyReason::InvalidToken => "InvalidToken",
+            DenyReason::HeaderAlgorithmUnspecified => "HeaderAlgorithmUnspecified",
+            DenyReason::KeyIdNotFound => "KeyIdNotFound",
+            DenyReason::KeyNotFound => "KeyNotFound",
+            DenyReason::AppleKeysUnavailable => "AppleKeysUnavailable",
+            DenyReason::IssuerMismatch => "IssuerMismatch",
+            DenyReason::ClientIdMismatch => "ClientIdMismatch",
+            DenyReason::NonceMismatch => "NonceMismatch",
+            DenyReason::SessionExpired => "SessionExpired",
+            DenyReason::InvalidSessionToken => "InvalidSessionToken",
+            DenyReason::NotAdmin => "NotAdmin",
+            DenyReason::BannedUser => "BannedUser",
+            DenyReason::BannedDevice => "BannedDevice",
+            DenyReason::UpgradeRequired => "UpgradeRequired",
+        }
+    }
+
+    /// What the caller is told.
+    pub fn message(&self) -> &'static str {
+        match self {
+            DenyReason::MissingAuthorizationHeader => "Missing Authorization header",
+            DenyReason::InvalidAuthorizationHeader => "Invalid Authorization header",
+            DenyReason::MissingUserIdHeader => "Missing UserId header",
+            DenyReason::MissingDeviceIdHeader => "Missing DeviceId header",
+            DenyReason::TokenExpired => "Token expired",
+            DenyReason::InvalidToken
+            | DenyReason::HeaderAlgorithmUnspecified
+            | DenyReason::KeyIdNotFound
+            | DenyReason::KeyNotFound
+            | DenyReason::AppleKeysUnavailable
+            | DenyReason::IssuerMismatch
+            | DenyReason::ClientIdMismatch
+            | DenyReason::NonceMismatch => "Invalid token",
+            DenyReason::SessionExpired => "Session expired",
+            DenyReason::InvalidSessionToken => "Invalid session token",
+            DenyReason::NotAdmin => "Not an admin",
+            DenyReason::BannedUser => "User banned",
+            DenyReason::BannedDevice => "Device banned",
+            DenyReason::UpgradeRequired => "Upgrade required",
+        }
+    }
+}
+
+impl Display for DenyReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl From<&sign_in_with_apple::Error> for DenyReason {
+    fn from(error: &sign_in_with_apple::Error) -> Self {
+        use sign_in_with_apple::Error;
+        match error {
+            Error::HeaderAlgorithmUnspecified => DenyReason::HeaderAlgorithmUnspecified,
+            Error::KidNotFound => DenyReason::KeyIdNotFound,
+            Error::KeyNotFound => DenyReason::KeyNotFound,
+            Error::AppleKeys | Error::SerdeJson(_) | Error::Hyper(_) | Error::Http(_) => DenyReason::AppleKeysUnavailable,
+            Error::IssClaimMismatch => DenyReason::IssuerMismatch,
+            Error::ClientIdMismatch => DenyReason::ClientIdMismatch,
+            Error::NonceMistmatch => DenyReason::NonceMismatch,
+            Error::Jwt(e) if matches!(e.kind(), ErrorKind::ExpiredSignature) => DenyReason::TokenExpired,
+            Error::Jwt(_) => DenyReason::InvalidToken,
+        }
+    }
+}
+
+impl From<&SessionError> for DenyReason {
+    fn from(error: &SessionError) -> Self {
+        match error {
+            SessionError::Expired => DenyReason::SessionExpired,
+            SessionError::Malformed | SessionError::InvalidSignature => DenyReason::InvalidSessionToken,
+        }
+    }
+}
+
+/// A [`DenyReason`] and, for token failures, the error behind it.
+#[derive(Debug)]
+pub struct Denial {
+    reason: DenyReason,
+    diagnostic: Option<String>,
+}
+
+impl Denial {
+
+    pub fn with_diagnostic(mut self, diagnostic: impl Display) -> Denial {
+        self.diagnostic = Some(diagnostic.to_string());
+        self
+    }
+
+    pub fn reason(&self) -> DenyReason {
+        self.reason
+    }
+    pub fn diagnostic(&self) -> Option<&str> {
+        self.diagnostic.as_deref()
+    }
+}
+
+impl Display for Denial {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.diagnostic {
+            Some(diagnostic) => write!(f, "{}: {diagnostic}", self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl From<DenyReason> for Denial {
+    fn from(reason: DenyReason) -> Self {
+        Denial { reason, diagnostic: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use snipsnap_lib::http::session::SessionError;
+
+    use crate::deny_reason::DenyReason;
+
+    #[test]
+    fn test_from_errors() {
+        assert_eq!(DenyReason::from(&sign_in_with_apple::Error::NonceMistmatch), DenyReason::NonceMismatch);
+        let expired = jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::ExpiredSignature);
+        assert_eq!(DenyReason::from(&sign_in_with_apple::Error::Jwt(expired)), DenyReason::TokenExpired);
+        assert_eq!(DenyReason::from(&SessionError::InvalidSignature), DenyReason::InvalidSessionToken);
+        assert_eq!(DenyReason::NonceMismatch.message(), "Invalid token");
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Replaying captured events without deploying, e.g.
+//! `cargo run -- local tests/real_input.json --jwks tests/local_jwks.json --nonce device=nonce`.
+//!
+//! Nonces come from the command line instead of DynamoDB, nobody is banned, and Apple id tokens are checked against a local JWKS when
+//! given one. Everything else, like `SNIPSNAP_SESSION_SECRET`, is read from the environment as in the lambda.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use lambda_runtime::Error;
+use serde_json::json;
+use sign_in_with_apple::{KeySource, Nonces, Validator};
+
+use authorizer_models::AuthorizerRequest;
+
+use crate::{Authorizer, decide, respond};
+use crate::bans::Bans;
+
+pub async fn run(event: Option<&Path>, jwks: Option<&Path>, nonces: Vec<(String, String)>) -> Result<(), Error> {
+    let input = match event {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+    let request: AuthorizerRequest = serde_json::from_str(&input)?;
+
+    let keys = match jwks {
+        Some(path) => KeySource::from_jwks(&fs::read(path)?)?,
+        None => KeySource::Apple,
+    };
+    let nonces = Nonces::memory(nonces.into_iter().collect::<HashMap<_, _>>());
+    let authorizer = Authorizer::new(Validator::new(keys, nonces), Bans::memory(Vec::new()))?;
+
+    let decision = decide(&authorizer, &request).await;
+    let denial = decision.as_ref().err();
+    let output = json!({
+        "response": respond(&authorizer, &request, &decision),
+        "denyReason": denial.map(|denial| denial.reason().name()),
+        "diagnostic": denial.and_then(|denial| denial.diagnostic()),
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// `DEVICE_ID=NONCE`
+pub fn parse_nonce(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((device_id, nonce)) if !device_id.is_empty() => Ok((String::from(device_id), String::from(nonce))),
+        _ => Err(format!("Expected DEVICE_ID=NONCE, got {arg}")),
+    }
+}
@@ -2,90 +2,372 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
 
+use clap::{Parser, Subcommand};
 use lambda_runtime::{Error, LambdaEvent, run, service_fn};
 
-use authorizer_models::{SimpleAuthorizerRequest, SimpleAuthorizerResponse};
-use sign_in_with_apple::validate;
+use authorizer_models::{
+    AuthorizerRequest, AuthorizerResponse, Headers, IamPolicyResponse, ResourceScope, SimpleAuthorizerResponse,
+};
+use sign_in_with_apple::Validator;
+use snipsnap_lib::database::{Database, Subject};
+use snipsnap_lib::http::{ClientVersion, ErrorDetail, MinimumVersions, SessionTokens};
+use snipsnap_lib::http::client_version::{CLIENT_VERSION_HEADER, USER_AGENT_HEADER};
+use snipsnap_lib::http::extract::{DEVICE_ID_CONTEXT_KEY, USER_ID_CONTEXT_KEY};
+use tracing::warn;
 
-use crate::values::{AUTHORIZATION_HEADER, DEVICE_ID_HEADER, TOKEN_PREFIX, USER_ID_HEADER};
+use crate::bans::Bans;
+use crate::deny_reason::{Denial, DenyReason};
+use crate::policy::{AuthLevel, Policy, policy};
+use crate::validation_cache::ValidationCache;
+use crate::values::{
+    ADMIN_USER_IDS_VARIABLE, ANONYMOUS_PRINCIPAL, AUTHORIZATION_HEADER, DEVICE_ID_HEADER, TOKEN_PREFIX,
+    UPGRADE_REQUIRED_CODE, USER_ID_HEADER,
+};
 
+mod bans;
+mod deny_reason;
+mod local;
+mod metrics;
+mod policy;
+mod validation_cache;
 mod values;
 
-async fn handler(event: LambdaEvent<SimpleAuthorizerRequest>) -> Result<SimpleAuthorizerResponse, Error> {
-    let mut context = HashMap::new();
+/// Everything the handler needs, created once per process.
+struct Authorizer {
+    validator: Validator,
+    validations: ValidationCache,
+    bans: Bans,
+    error_detail: ErrorDetail,
+    minimum_versions: MinimumVersions,
+    policy: Policy,
+    sessions: SessionTokens,
+    admin_user_ids: Vec<String>,
+}
 
-    // get headers
-    let headers = event.payload.headers();
-    let authorization;
-    match headers.get(AUTHORIZATION_HEADER) {
-        Some(value) => {
-            match value.strip_prefix(TOKEN_PREFIX) {
-                Some(v) => authorization = String::from(v),
-                None => {
-                    context.insert(String::from("failure"), String::from("Invalid Authorization header"));
-                    return Ok(SimpleAuthorizerResponse::new(false, context));
-                }
-            }
+impl Authorizer {
+    /// Checks Apple id tokens with `validator` and bans with `bans`, and reads everything else from the environment.
+    fn new(validator: Validator, bans: Bans) -> Result<Authorizer, Error> {
+        Ok(Authorizer {
+            validator,
+            validations: ValidationCache::new(),
+            bans,
+            error_detail: ErrorDetail::from_env(),
+            minimum_versions: MinimumVersions::from_env(),
+            policy: policy(),
+            sessions: SessionTokens::from_env()?,
+            // comma separated
+            admin_user_ids: env::var(ADMIN_USER_IDS_VARIABLE)
+                .map(|ids| ids.split(',').map(str::trim).filter(|id| !id.is_empty()).map(String::from).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    fn from_env() -> Result<Authorizer, Error> {
+        let database = Database::from_env()?;
+        Authorizer::new(Validator::apple(database.clone()), Bans::new(database))
+    }
+}
+
+/// The context to authorize with, or why not.
+type Decision = Result<HashMap<String, String>, Denial>;
+
+/// Answers HTTP APIs with simple responses and REST APIs with IAM policies, so the same binary works behind either.
+async fn handler(authorizer: &Authorizer, event: LambdaEvent<AuthorizerRequest>) -> Result<AuthorizerResponse, Error> {
+    let decision = decide(authorizer, &event.payload).await;
+    if let Err(denial) = &decision {
+        // only HTTP APIs describe the call beyond its ARN
+        let request_context = match &event.payload {
+            AuthorizerRequest::Simple(request) => Some(request.request_context()),
+            _ => None,
+        };
+        warn!(
+            reason = denial.reason().name(),
+            requestId = event.context.request_id,
+            resource = event.payload.resource_arn(),
+            apiRequestId = request_context.map(|context| context.request_id()),
+            sourceIp = request_context.map(|context| context.http().source_ip()),
+            method = request_context.map(|context| context.http().method()),
+            stage = request_context.map(|context| context.stage()),
+            diagnostic = denial.diagnostic(),
+            "Denied"
+        );
+        metrics::record_denial(denial.reason());
+    }
+    Ok(respond(authorizer, &event.payload, &decision))
+}
+
+async fn decide(authorizer: &Authorizer, request: &AuthorizerRequest) -> Decision {
+    // names are matched ignoring case, HTTP APIs send them lowercase
+    let headers = &headers(request);
+
+    let identity = match authorizer.policy.level(request) {
+        AuthLevel::Anonymous => return Ok(HashMap::new()),
+        AuthLevel::AppleIdToken => apple_id_token(authorizer, headers).await,
+        AuthLevel::SessionToken => session_token(authorizer, headers, false),
+        AuthLevel::Admin => session_token(authorizer, headers, true),
+    };
+    // authenticated outdated builds are let through for the handler's Router to answer with a 426, since API Gateway
+    // answers every deny with 403, and the rest are told to upgrade
+    let client_version = ClientVersion::parse(headers.get(CLIENT_VERSION_HEADER), headers.get(USER_AGENT_HEADER));
+    let (user_id, device_id) = match identity {
+        Err(denial) if !authorizer.minimum_versions.is_supported(client_version.as_ref()) => {
+            return Err(Denial::from(DenyReason::UpgradeRequired).with_diagnostic(denial));
         }
-        None => {
-            context.insert(String::from("failure"), String::from("Missing Authorization header"));
-            return Ok(SimpleAuthorizerResponse::new(false, context));
+        identity => identity?,
+    };
+    check_bans(authorizer, &user_id, &device_id).await?;
+    Ok(user_context(user_id, device_id))
+}
+
+fn respond(authorizer: &Authorizer, request: &AuthorizerRequest, decision: &Decision) -> AuthorizerResponse {
+    let (is_authorized, context) = match decision {
+        Ok(context) => (true, context.clone()),
+        Err(denial) => {
+            // the diagnostic stays in the logs unless this stage is verbose
+            let reason = denial.reason();
+            let failure = match (&authorizer.error_detail, denial.diagnostic()) {
+                (ErrorDetail::Verbose, Some(diagnostic)) => format!("{}: {diagnostic}", reason.message()),
+                _ => String::from(reason.message()),
+            };
+            let mut context = HashMap::from([(String::from("failure"), failure)]);
+            if reason == DenyReason::UpgradeRequired {
+                // the code the handlers answer outdated builds with, for a gateway response to match on
+                context.insert(String::from("code"), String::from(UPGRADE_REQUIRED_CODE));
+            }
+            (false, context)
         }
+    };
+    if !request.expects_policy() {
+        return SimpleAuthorizerResponse::new(is_authorized, context).into();
     }
-    let user_id;
-    match headers.get(USER_ID_HEADER) {
-        Some(value) => user_id = String::from(value),
-        None => {
-            context.insert(String::from("failure"), String::from("Missing UserId header"));
-            return Ok(SimpleAuthorizerResponse::new(false, context));
-        }
+
+    let principal_id = context.get(USER_ID_CONTEXT_KEY).cloned().unwrap_or_else(|| String::from(ANONYMOUS_PRINCIPAL));
+    let builder = IamPolicyResponse::builder(principal_id, request.resource_arn());
+    let builder = match is_authorized {
+        true => builder.allow(ResourceScope::Route),
+        false => builder.deny(ResourceScope::Route),
+    };
+    let builder = match is_cacheable(authorizer, request, decision) {
+        true => builder.cacheable(),
+        false => builder,
+    };
+    context.into_iter()
+        .fold(builder, |builder, (key, value)| builder.context(key, value))
+        .build()
+        .into()
+}
+
+/// Whether the decision holds for every route the gateway could reuse it for, which it caches by identity source.
+///
+/// Only TOKEN requests qualify, since their identity source is all the authorizer sees, and only when they're allowed,
+/// so a deny from e.g. a failed ban lookup doesn't lock the token out of the stage. The policy is widened to the whole
+/// stage, so it must only be cached if the session token passes on every route: none may need an Apple id token, like
+/// `/login`, and none may need an admin unless the user is one. A ban is reused for as long as the gateway caches it,
+/// like the ban lookups here.
+fn is_cacheable(authorizer: &Authorizer, request: &AuthorizerRequest, decision: &Decision) -> bool {
+    if !matches!(request, AuthorizerRequest::Token(_)) {
+        return false;
     }
-    let device_id;
-    match headers.get(DEVICE_ID_HEADER) {
-        Some(value) => device_id = String::from(value),
-        None => {
-            context.insert(String::from("failure"), String::from("Missing DeviceId header"));
-            return Ok(SimpleAuthorizerResponse::new(false, context));
+    if !matches!(authorizer.policy.level(request), AuthLevel::SessionToken | AuthLevel::Admin) {
+        return false;
+    }
+    let Ok(context) = decision else {
+        return false;
+    };
+    let is_admin = context.get(USER_ID_CONTEXT_KEY).is_some_and(|user_id| authorizer.admin_user_ids.contains(user_id));
+    authorizer.policy.levels().all(|level| match level {
+        AuthLevel::Anonymous | AuthLevel::SessionToken => true,
+        AuthLevel::AppleIdToken => false,
+        AuthLevel::Admin => is_admin,
+    })
+}
+
+/// The request's headers. TOKEN authorizers are only sent their identity source, which is the Authorization header.
+fn headers(request: &AuthorizerRequest) -> Cow<'_, Headers> {
+    match request {
+        AuthorizerRequest::Simple(request) => Cow::Borrowed(request.headers()),
+        AuthorizerRequest::Request(request) => Cow::Borrowed(request.headers()),
+        AuthorizerRequest::Token(request) => {
+            let mut headers = Headers::new();
+            headers.append(AUTHORIZATION_HEADER, request.authorization_token());
+            Cow::Owned(headers)
         }
     }
+}
+
+fn bearer_token(headers: &Headers) -> Result<&str, Denial> {
+    match headers.get(AUTHORIZATION_HEADER) {
+        Some(value) => value.strip_prefix(TOKEN_PREFIX).ok_or_else(|| DenyReason::InvalidAuthorizationHeader.into()),
+        None => Err(DenyReason::MissingAuthorizationHeader.into()),
+    }
+}
+
+/// Who the token was issued to, by user id and device id.
+type Identity = Result<(String, String), Denial>;
+
+/// Sign In With Apple, for `/login`.
+async fn apple_id_token(authorizer: &Authorizer, headers: &Headers) -> Identity {
+    let authorization = bearer_token(headers)?;
+    let user_id = headers.get(USER_ID_HEADER).ok_or(DenyReason::MissingUserIdHeader)?;
+    let device_id = headers.get(DEVICE_ID_HEADER).ok_or(DenyReason::MissingDeviceIdHeader)?;
+
+    // a token this container already validated skips Apple's keys, but still uses up the nonce
+    if let Some(nonce) = authorizer.validations.get(authorization, user_id, device_id) {
+        return match authorizer.validator.check_nonce(device_id, &nonce).await {
+            Ok(()) => Ok((String::from(user_id), String::from(device_id))),
+            Err(e) => {
+                authorizer.validations.remove(authorization, device_id);
+                Err(Denial::from(DenyReason::from(&e)).with_diagnostic(e))
+            }
+        };
+    }
 
     // validate
-    match validate(user_id, authorization, device_id, false).await {
-        Ok(_) => Ok(SimpleAuthorizerResponse::new(true, context)),
-        Err(e) => {
-            context.insert(String::from("failure"), format!("Token validation error: {e}"));
-            Ok(SimpleAuthorizerResponse::new(false, context))
+    match authorizer.validator.validate(user_id, authorization, device_id, false).await {
+        Ok(token) => {
+            let claims = &token.claims;
+            authorizer.validations.insert(authorization, user_id, device_id, &claims.aud, i64::from(claims.exp));
+            Ok((String::from(user_id), String::from(device_id)))
         }
+        Err(e) => Err(Denial::from(DenyReason::from(&e)).with_diagnostic(e)),
+    }
+}
+
+/// A session token from `/login`, for everything else.
+fn session_token(authorizer: &Authorizer, headers: &Headers, admin: bool) -> Identity {
+    let session = authorizer.sessions.verify(bearer_token(headers)?)
+        .map_err(|e| Denial::from(DenyReason::from(&e)).with_diagnostic(e))?;
+    if admin && !authorizer.admin_user_ids.iter().any(|id| id == session.user_id()) {
+        return Err(DenyReason::NotAdmin.into());
     }
+    Ok((String::from(session.user_id()), String::from(session.device_id())))
+}
+
+/// Whether the user or the device has been banned since the token was issued.
+async fn check_bans(authorizer: &Authorizer, user_id: &str, device_id: &str) -> Result<(), Denial> {
+    let user = Subject::User(String::from(user_id));
+    let device = Subject::Device(String::from(device_id));
+    let (reason, ban) = match tokio::join!(authorizer.bans.find(&user), authorizer.bans.find(&device)) {
+        (Some(ban), _) => (DenyReason::BannedUser, ban),
+        (None, Some(ban)) => (DenyReason::BannedDevice, ban),
+        (None, None) => return Ok(()),
+    };
+    // so the ban doesn't depend on the cached token expiring
+    authorizer.validations.invalidate(ban.subject());
+    Err(Denial::from(reason).with_diagnostic(ban.reason()))
+}
+
+/// Read by the AuthenticatedUser extractor in the http handlers.
+fn user_context(user_id: String, device_id: String) -> HashMap<String, String> {
+    HashMap::from([
+        (String::from(USER_ID_CONTEXT_KEY), user_id),
+        (String::from(DEVICE_ID_CONTEXT_KEY), device_id),
+    ])
+}
+
+#[derive(Parser)]
+#[command(about = "Lambda authorizer for SnipSnap, run without a command by the lambda runtime")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Authorize a captured authorizer request locally and print the response and deny reason
+    Local {
+        /// The request's json, read from stdin when omitted
+        event: Option<PathBuf>,
+        /// JWKS to check Apple id tokens with instead of Apple's keys, e.g. tests/local_jwks.json
+        #[arg(long)]
+        jwks: Option<PathBuf>,
+        /// A nonce from /get-nonce, as DEVICE_ID=NONCE. Can be repeated
+        #[arg(long = "nonce", value_name = "DEVICE_ID=NONCE", value_parser = local::parse_nonce)]
+        nonces: Vec<(String, String)>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+    let local = cli.command.is_some();
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         // disable printing the name of the module in every log line.
         .with_target(false)
         // disabling time is handy because CloudWatch will add the ingestion time.
         .without_time()
+        // keep stdout for the result when running locally
+        .with_writer(move || -> Box<dyn std::io::Write> {
+            if local { Box::new(std::io::stderr()) } else { Box::new(std::io::stdout()) }
+        })
         .init();
 
-    run(service_fn(handler)).await
+    match cli.command {
+        Some(Command::Local { event, jwks, nonces }) => local::run(event.as_deref(), jwks.as_deref(), nonces).await,
+        None => {
+            let authorizer = Authorizer::from_env()?;
+            let authorizer = &authorizer;
+            run(service_fn(move |event| handler(authorizer, event))).await
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use lambda_runtime::{Context, LambdaEvent};
+    use std::collections::HashMap;
+
+    use authorizer_models::{AuthorizerRequest, AuthorizerResponse, Effect, SimpleAuthorizerRequest};
+    use chrono::Utc;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde_json::json;
+    use sign_in_with_apple::{Claims, KeySource, Nonces, Validator};
+    use snipsnap_lib::database::{Ban, Database, DatabaseConfig, Subject};
+    use snipsnap_lib::http::{ErrorDetail, MinimumVersions, SessionTokens};
+    use snipsnap_lib::http::client_version::{Build, Platform};
+
+    use crate::{Authorizer, decide, handler};
+    use crate::bans::Bans;
+    use crate::deny_reason::DenyReason;
+    use crate::validation_cache::ValidationCache;
+    use crate::policy::{AuthLevel, Policy, policy};
+
+    const SESSION_SECRET: &[u8] = b"secret";
 
-    use crate::{handler, SimpleAuthorizerRequest};
+    /// Checks Apple id tokens on every route, like before routes had policies.
+    fn authorizer() -> Authorizer {
+        Authorizer {
+            validator: Validator::apple(Database::new(DatabaseConfig::default().with_stage("test")).expect("Failed to create database")),
+            validations: ValidationCache::new(),
+            bans: Bans::memory(Vec::new()),
+            error_detail: ErrorDetail::Public,
+            minimum_versions: MinimumVersions::default(),
+            policy: Policy::new(AuthLevel::AppleIdToken),
+            sessions: SessionTokens::new(SESSION_SECRET),
+            admin_user_ids: vec![String::from("admin")],
+        }
+    }
+
+    /// A request to `route_key` with `headers`.
+    fn request(route_key: &str, headers: &[(&str, &str)]) -> LambdaEvent<AuthorizerRequest> {
+        let payload = headers.iter()
+            .fold(SimpleAuthorizerRequest::builder().route_key(route_key), |builder, (name, value)| builder.header(name, value))
+            .build();
+        LambdaEvent::new(AuthorizerRequest::Simple(payload), Context::default())
+    }
 
     #[tokio::test]
     async fn test_missing_header() {
         let input_str = include_str!("../tests/missing_header.json");
-        let payload: SimpleAuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
         let request = LambdaEvent::new(payload, Context::default());
-        let response = handler(request).await.expect("Failed to handle request");
+        let response = handler(&authorizer(), request).await.expect("Failed to handle request");
         assert!(!response.is_authorized());
         assert_eq!(response.context().len(), 1);
         assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing Authorization header");
@@ -94,9 +376,9 @@ mod test {
     #[tokio::test]
     async fn test_not_allowed() {
         let input_str = include_str!("../tests/not_allowed.json");
-        let payload: SimpleAuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
         let request = LambdaEvent::new(payload, Context::default());
-        let response = handler(request).await.expect("Failed to handle request");
+        let response = handler(&authorizer(), request).await.expect("Failed to handle request");
         assert!(!response.is_authorized());
         assert_eq!(response.context().len(), 1);
         assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing Authorization header");
@@ -105,22 +387,270 @@ mod test {
     #[tokio::test]
     async fn test_has_auth_header() {
         let input_str = include_str!("../tests/has_auth_header.json");
-        let payload: SimpleAuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
         let request = LambdaEvent::new(payload, Context::default());
-        let response = handler(request).await.expect("Failed to handle request");
+        let response = handler(&authorizer(), request).await.expect("Failed to handle request");
         assert!(!response.is_authorized());
         assert_eq!(response.context().len(), 1);
         assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing UserId header");
     }
 
+    #[tokio::test]
+    async fn test_old_client() {
+        let input_str = include_str!("../tests/old_client.json");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let request = LambdaEvent::new(payload, Context::default());
+        let mut authorizer = authorizer();
+        authorizer.minimum_versions = MinimumVersions::default()
+            .with_minimum(Platform::Ios, Build::parse("121").expect("Invalid build"));
+        let response = handler(&authorizer, request).await.expect("Failed to handle request");
+        assert!(!response.is_authorized(), "Let an outdated client through without credentials");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Upgrade required");
+        assert_eq!(response.context().get("code").expect("Missing context variable"), "UPGRADE_REQUIRED");
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_old_client() {
+        let mut authorizer = authorizer();
+        authorizer.policy = policy();
+        authorizer.minimum_versions = MinimumVersions::default()
+            .with_minimum(Platform::Ios, Build::parse("121").expect("Invalid build"));
+        let bearer = format!("Bearer {}", SessionTokens::new(SESSION_SECRET).issue("user", "device").0);
+        let headers = [("authorization", bearer.as_str()), ("x-client-version", "ios/120")];
+
+        // let through for the handler to answer with a 426
+        let response = handler(&authorizer, request("GET /friends", &headers)).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+        assert_eq!(response.context().get("userId").expect("Missing context variable"), "user");
+    }
+
+    #[tokio::test]
+    async fn test_real_input_with_auth_headers() {
+        let input_str = include_str!("../tests/real_input_with_auth_headers.json");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let request = LambdaEvent::new(payload, Context::default());
+        let response = handler(&authorizer(), request).await.expect("Failed to handle request");
+        assert!(!response.is_authorized());
+        assert_eq!(response.context().len(), 1);
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Invalid token");
+    }
+
     #[tokio::test]
     async fn test_real_input() {
         let input_str = include_str!("../tests/real_input.json");
-        let payload: SimpleAuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
+        let payload: AuthorizerRequest = serde_json::from_str(input_str).expect("Failed to deserialize request");
         let request = LambdaEvent::new(payload, Context::default());
-        let response = handler(request).await.expect("Failed to handle request");
+        let response = handler(&authorizer(), request).await.expect("Failed to handle request");
         assert!(!response.is_authorized());
         assert_eq!(response.context().len(), 1);
         assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing Authorization header");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_route_policies() {
+        let mut authorizer = authorizer();
+        authorizer.policy = policy();
+
+        let response = handler(&authorizer, request("POST /get-nonce", &[])).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+        let response = handler(&authorizer, request("OPTIONS /{proxy+}", &[])).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+
+        let apple = [("authorization", "Bearer true")];
+        let response = handler(&authorizer, request("POST /login", &apple)).await.expect("Failed to handle request");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing UserId header");
+        let response = handler(&authorizer, request("POST /friends", &apple)).await.expect("Failed to handle request");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Invalid session token");
+
+        // the $default route on a named stage sees the stage in the path
+        let staged = |path: &str| {
+            let payload = SimpleAuthorizerRequest::builder().route_key("$default").http("POST", path).stage("dev");
+            LambdaEvent::new(AuthorizerRequest::Simple(payload.header("authorization", "Bearer true").build()), Context::default())
+        };
+        let response = handler(&authorizer, staged("/dev/get-nonce")).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+        let response = handler(&authorizer, staged("/dev/v1/login")).await.expect("Failed to handle request");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Missing UserId header");
+        let response = handler(&authorizer, staged("/dev/friends")).await.expect("Failed to handle request");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Invalid session token");
+    }
+
+    #[tokio::test]
+    async fn test_session_token() {
+        let mut authorizer = authorizer();
+        authorizer.policy = policy().route("*", "/admin", AuthLevel::Admin);
+        let bearer = |user_id| format!("Bearer {}", SessionTokens::new(SESSION_SECRET).issue(user_id, "device").0);
+
+        let user = bearer("user");
+        let response = handler(&authorizer, request("POST /v1/friends", &[("authorization", &user)])).await
+            .expect("Failed to handle request");
+        assert!(response.is_authorized());
+        assert_eq!(response.context().get("userId").expect("Missing context variable"), "user");
+        assert_eq!(response.context().get("deviceId").expect("Missing context variable"), "device");
+
+        let response = handler(&authorizer, request("GET /admin", &[("authorization", &user)])).await
+            .expect("Failed to handle request");
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Not an admin");
+        let admin = bearer("admin");
+        let response = handler(&authorizer, request("GET /admin", &[("authorization", &admin)])).await
+            .expect("Failed to handle request");
+        assert!(response.is_authorized());
+    }
+
+    #[tokio::test]
+    async fn test_rest_token() {
+        let mut authorizer = authorizer();
+        authorizer.policy = Policy::new(AuthLevel::SessionToken).route("OPTIONS", "*", AuthLevel::Anonymous);
+        let arn = "arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/GET/friends";
+        let token_request = |token: &str| {
+            let event = json!({ "type": "TOKEN", "authorizationToken": token, "methodArn": arn });
+            let payload: AuthorizerRequest = serde_json::from_value(event).expect("Failed to deserialize request");
+            LambdaEvent::new(payload, Context::default())
+        };
+        let resources = |response: &AuthorizerResponse| match response {
+            AuthorizerResponse::Policy(iam) => iam.policy_document().statements()[0].resources().clone(),
+            _ => panic!("Answered a TOKEN request without a policy"),
+        };
+
+        let bearer = format!("Bearer {}", SessionTokens::new(SESSION_SECRET).issue("user", "device").0);
+        let response = handler(&authorizer, token_request(&bearer)).await.expect("Failed to handle request");
+        let AuthorizerResponse::Policy(iam) = &response else {
+            panic!("Answered a TOKEN request without a policy");
+        };
+        assert!(iam.is_authorized());
+        assert_eq!(iam.principal_id(), "user");
+        // cached for the whole stage, since every route takes the same session token
+        let stage = "arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/*";
+        assert_eq!(resources(&response), vec![String::from(stage)]);
+        assert_eq!(response.context().get("userId").expect("Missing context variable"), "user");
+        assert_eq!(response.context().get("deviceId").expect("Missing context variable"), "device");
+
+        // denials aren't cached
+        let response = handler(&authorizer, token_request("Bearer forged")).await.expect("Failed to handle request");
+        let AuthorizerResponse::Policy(iam) = &response else {
+            panic!("Answered a TOKEN request without a policy");
+        };
+        assert_eq!(iam.policy_document().statements()[0].effect(), Effect::Deny);
+        assert_eq!(iam.principal_id(), "anonymous");
+        assert_eq!(resources(&response), vec![String::from(arn)]);
+        assert_eq!(response.context().get("failure").expect("Missing context variable"), "Invalid session token");
+
+        // with admin routes, only admins are the same on every route
+        authorizer.policy = Policy::new(AuthLevel::SessionToken).route("*", "/admin", AuthLevel::Admin);
+        let response = handler(&authorizer, token_request(&bearer)).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+        assert_eq!(resources(&response), vec![String::from(arn)]);
+    }
+
+    #[tokio::test]
+    async fn test_cached_session_excludes_login() {
+        let mut authorizer = authorizer();
+        authorizer.policy = policy();
+        let event = json!({
+            "type": "TOKEN",
+            "authorizationToken": format!("Bearer {}", SessionTokens::new(SESSION_SECRET).issue("user", "device").0),
+            "methodArn": "arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/GET/friends",
+        });
+        let payload: AuthorizerRequest = serde_json::from_value(event).expect("Failed to deserialize request");
+        let response = handler(&authorizer, LambdaEvent::new(payload, Context::default())).await
+            .expect("Failed to handle request");
+        let AuthorizerResponse::Policy(iam) = &response else {
+            panic!("Answered a TOKEN request without a policy");
+        };
+        assert!(iam.is_authorized());
+        // a policy the gateway reuses for /login would skip the Apple id token
+        let login = "arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/POST/login";
+        let covers = |resource: &String| resource == login
+            || resource.strip_suffix('*').is_some_and(|prefix| login.starts_with(prefix));
+        assert!(!iam.policy_document().statements()[0].resources().iter().any(covers), "Cached a session for /login");
+    }
+
+    /// An Apple style id token for `user_id`, signed with `tests/local_key.pem`.
+    fn local_id_token(user_id: &str, nonce: &str) -> String {
+        let now = Utc::now().timestamp() as i32;
+        let claims = Claims {
+            iss: String::from("https://appleid.apple.com"),
+            aud: String::from(nonce),
+            exp: now + 60,
+            iat: now,
+            sub: String::from(user_id),
+            c_hash: String::new(),
+            email: None,
+            email_verified: None,
+            auth_time: now,
+            nonce: String::from(nonce),
+        };
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(String::from("local"));
+        let key = EncodingKey::from_rsa_pem(include_bytes!("../tests/local_key.pem")).expect("Invalid key");
+        jsonwebtoken::encode(&header, &claims, &key).expect("Failed to sign token")
+    }
+
+    #[tokio::test]
+    async fn test_local_keys() {
+        let mut authorizer = authorizer();
+        let keys = KeySource::from_jwks(include_bytes!("../tests/local_jwks.json")).expect("Invalid JWKS");
+        let nonces = Nonces::memory(HashMap::from([
+            (String::from("device"), String::from("nonce")),
+            (String::from("other-device"), String::from("nonce")),
+        ]));
+        authorizer.validator = Validator::new(keys, nonces);
+        let bearer = |nonce| format!("Bearer {}", local_id_token("user", nonce));
+
+        let other = bearer("other");
+        let headers = [("authorization", other.as_str()), ("x-userid", "user"), ("x-deviceid", "other-device")];
+        let decision = decide(&authorizer, &request("POST /login", &headers).payload).await;
+        assert_eq!(decision.expect_err("Authorized with the wrong nonce").reason(), DenyReason::NonceMismatch);
+
+        let valid = bearer("nonce");
+        let headers = [("authorization", valid.as_str()), ("x-userid", "user"), ("x-deviceid", "device")];
+        let response = handler(&authorizer, request("POST /login", &headers)).await.expect("Failed to handle request");
+        assert!(response.is_authorized());
+        assert_eq!(response.context().get("userId").expect("Missing context variable"), "user");
+
+        // cached, so no longer needs the keys
+        authorizer.validator = Validator::new(KeySource::Local(HashMap::new()), Nonces::memory(HashMap::new()));
+        assert!(decide(&authorizer, &request("POST /login", &headers).payload).await.is_ok());
+        // but still checks the nonce the device holds now
+        let nonces = Nonces::memory(HashMap::from([(String::from("device"), String::from("newer"))]));
+        authorizer.validator = Validator::new(KeySource::Local(HashMap::new()), nonces);
+        let decision = decide(&authorizer, &request("POST /login", &headers).payload).await;
+        assert_eq!(decision.expect_err("Replayed a cached token").reason(), DenyReason::NonceMismatch);
+        let decision = decide(&authorizer, &request("POST /login", &headers).payload).await;
+        assert_eq!(decision.expect_err("Left a replayed token cached").reason(), DenyReason::KeyNotFound);
+        let keys = KeySource::from_jwks(include_bytes!("../tests/local_jwks.json")).expect("Invalid JWKS");
+        authorizer.validator = Validator::new(keys, Nonces::memory(HashMap::new()));
+        assert!(decide(&authorizer, &request("POST /login", &headers).payload).await.is_ok());
+        authorizer.validator = Validator::new(KeySource::Local(HashMap::new()), Nonces::memory(HashMap::new()));
+        authorizer.bans = Bans::memory(vec![Ban::new(Subject::User(String::from("user")), "spam", None)]);
+        let decision = decide(&authorizer, &request("POST /login", &headers).payload).await;
+        assert_eq!(decision.expect_err("Authorized a banned user").reason(), DenyReason::BannedUser);
+        authorizer.bans = Bans::memory(Vec::new());
+        let decision = decide(&authorizer, &request("POST /login", &headers).payload).await;
+        assert_eq!(decision.expect_err("Ban left the token cached").reason(), DenyReason::KeyNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_bans() {
+        let mut authorizer = authorizer();
+        authorizer.policy = policy();
+        authorizer.bans = Bans::memory(vec![
+            Ban::new(Subject::User(String::from("banned")), "spam", None),
+            Ban::new(Subject::Device(String::from("stolen")), "reported stolen", Some(Utc::now() + chrono::Duration::days(1))),
+            Ban::new(Subject::User(String::from("forgiven")), "spam", Some(Utc::now() - chrono::Duration::days(1))),
+        ]);
+        let tokens = SessionTokens::new(SESSION_SECRET);
+        let session_request = |user_id: &str, device_id: &str| {
+            let bearer = format!("Bearer {}", tokens.issue(user_id, device_id).0);
+            request("GET /friends", &[("authorization", &bearer)]).payload
+        };
+
+        let decision = decide(&authorizer, &session_request("banned", "device")).await;
+        let denial = decision.expect_err("Authorized a banned user");
+        assert_eq!(denial.reason(), DenyReason::BannedUser);
+        assert_eq!(denial.diagnostic(), Some("spam"));
+        let decision = decide(&authorizer, &session_request("user", "stolen")).await;
+        assert_eq!(decision.expect_err("Authorized a banned device").reason(), DenyReason::BannedDevice);
+        assert!(decide(&authorizer, &session_request("forgiven", "device")).await.is_ok());
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! CloudWatch metrics in the Embedded Metric Format: JSON lines on stdout that CloudWatch Logs turns into metrics,
+//! without calling PutMetricData from the lambda.
+
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::deny_reason::DenyReason;
+
+const NAMESPACE: &str = "SnipSnap/Authorizer";
+const DENIED_METRIC: &str = "Denied";
+const REASON_DIMENSION: &str = "Reason";
+
+/// Count a denial under its reason.
+pub fn record_denial(reason: DenyReason) {
+    // printed directly, the log formatter would wrap it in a line CloudWatch can't parse
+    println!("{}", denial_document(reason));
+}
+
+fn denial_document(reason: DenyReason) -> Value {
+    json!({
+        "_aws": {
+            "Timestamp": Utc::now().timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": NAMESPACE,
+                "Dimensions": [[REASON_DIMENSION]],
+                "Metrics": [{ "Name": DENIED_METRIC, "Unit": "Count" }],
+            }],
+        },
+        REASON_DIMENSION: reason.name(),
+        DENIED_METRIC: 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deny_reason::DenyReason;
+    use crate::metrics::denial_document;
+
+    #[test]
+    fn test_denial_document() {
+        let document = denial_document(DenyReason::NonceMismatch);
+        assert_eq!(document["Reason"], "NonceMismatch");
+        assert_eq!(document["Denied"], 1);
+        assert_eq!(document["_aws"]["CloudWatchMetrics"][0]["Dimensions"][0][0], "Reason");
+        assert_eq!(document["_aws"]["CloudWatchMetrics"][0]["Metrics"][0]["Name"], "Denied");
+    }
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Which credentials each route needs, so every route can sit behind this one authorizer.
+//!
+//! Routes that allow anonymous callers only reach the authorizer if it has no identity sources, since API Gateway
+//! rejects requests missing any of them before asking.
+
+use authorizer_models::{AuthorizerRequest, RouteArn, SimpleAuthorizerRequest};
+
+/// What a caller has to prove, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AuthLevel {
+    Anonymous,
+    /// An id token from Sign In With Apple, with the `X-UserId` and `X-DeviceId` it was issued for.
+    AppleIdToken,
+    /// A session token issued by `/login`.
+    SessionToken,
+    /// A session token of one of the admin users.
+    #[allow(dead_code)] // for the admin routes to come
+    Admin,
+}
+
+struct Rule {
+    method: String,
+    path: String,
+    level: AuthLevel,
+}
+
+impl Rule {
+    fn matches(&self, method: &str, path: &str) -> bool {
+        (self.method == ANY || self.method.eq_ignore_ascii_case(method)) && (self.path == ANY || self.path == path)
+    }
+}
+
+/// The level each route needs. The first matching rule wins, and routes matching none need the default.
+pub struct Policy {
+    rules: Vec<Rule>,
+    default: AuthLevel,
+}
+
+const ANY: &str = "*";
+
+impl Policy {
+
+    pub fn new(default: AuthLevel) -> Policy {
+        Policy {
+            rules: Vec::new(),
+            default,
+        }
+    }
+
+    /// Require `level` for `method` and `path`, either of which can be `*`. Paths are matched without any `/v{n}`
+    /// version prefix.
+    pub fn route(mut self, method: &str, path: &str, level: AuthLevel) -> Policy {
+        self.rules.push(Rule {
+            method: String::from(method),
+            path: String::from(path),
+            level,
+        });
+        self
+    }
+
+    /// Every level some route needs.
+    pub fn levels(&self) -> impl Iterator<Item = AuthLevel> + '_ {
+        std::iter::once(self.default).chain(self.rules.iter().map(|rule| rule.level))
+    }
+
+    pub fn level(&self, request: &AuthorizerRequest) -> AuthLevel {
+        let (method, path) = route(request);
+        let path = unversioned(&path);
+        self.rules.iter()
+            .find(|rule| rule.matches(&method, path))
+            .map_or(self.default, |rule| rule.level)
+    }
+}
+
+/// SnipSnap's routes.
+pub fn policy() -> Policy {
+    Policy::new(AuthLevel::SessionToken)
+        // CORS preflights never carry credentials
+        .route("OPTIONS", ANY, AuthLevel::Anonymous)
+        .route("POST", "/get-nonce", AuthLevel::Anonymous)
+        .route("POST", "/login", AuthLevel::AppleIdToken)
+}
+
+/// The method and path being called, e.g. `POST` and `/login`.
+fn route(request: &AuthorizerRequest) -> (String, String) {
+    match request {
+        AuthorizerRequest::Simple(request) => simple_route(request),
+        AuthorizerRequest::Request(request) => {
+            // HTTP APIs send payload format 1.0 with the stage in the path, REST APIs without
+            let stage = request.request_context().get("stage").and_then(|stage| stage.as_str());
+            let path = match (request.version(), stage) {
+                (Some(_), Some(stage)) => unstaged(request.path(), stage),
+                _ => request.path(),
+            };
+            (String::from(request.http_method()), String::from(path))
+        }
+        // TOKEN requests only have the method ARN, e.g. `.../prod/GET/friends`
+        AuthorizerRequest::Token(request) => match RouteArn::parse(request.method_arn()) {
+            Some(arn) => match arn.route().split_once('/') {
+                Some((method, path)) => (String::from(method), format!("/{path}")),
+                None => (String::from(arn.route()), String::from("/")),
+            },
+            None => (String::new(), String::new()),
+        },
+    }
+}
+
+/// The method and path of the route key, e.g. `POST /login`. The request's own are used for the `$default` route,
+/// `ANY` methods and paths with parameters, without the stage that named stages put in front of it.
+fn simple_route(request: &SimpleAuthorizerRequest) -> (String, String) {
+    let context = request.request_context();
+    let http_path = unstaged(context.http().path(), context.stage());
+    let (method, path) = match request.route_key().split_once(' ') {
+        Some((method, path)) => (
+            if method == "ANY" { context.http().method() } else { method },
+            if path.contains('{') { http_path } else { path },
+        ),
+        None => (context.http().method(), http_path),
+    };
+    (String::from(method), String::from(path))
+}
+
+/// `path` without a leading `/{stage}`, e.g. `/login` for `/dev/login`. The `$default` stage is never in the path.
+fn unstaged<'a>(path: &'a str, stage: &str) -> &'a str {
+    if stage.is_empty() || stage == "$default" {
+        return path;
+    }
+    match path.strip_prefix('/').and_then(|path| path.strip_prefix(stage)) {
+        Some("") => "/",
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => path,
+    }
+}
+
+fn unversioned(path: &str) -> &str {
+    let trimmed = path.trim_start_matches('/');
+    let end = trimmed.find('/').unwrap_or(trimmed.len());
+    let is_version = trimmed[..end].strip_prefix(['v', 'V'])
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()));
+    match (is_version, end == trimmed.len()) {
+        (true, true) => "/",
+        (true, false) => &trimmed[end..],
+        (false, _) => path,
+    }
+}
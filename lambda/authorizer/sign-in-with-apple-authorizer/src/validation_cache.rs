@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Apple id tokens this lambda has already validated, so a warm container doesn't fetch Apple's keys and decode the
+//! token again for a token it has seen.
+//!
+//! Entries are keyed on a hash of the token and device id, so the cache never holds a usable token, and last until the
+//! token expires. They remember the token's nonce, and the device's nonce is still used up and compared with it on
+//! every request, so a cached token passes the nonce check exactly when it would uncached.
+//!
+//! Users and devices are revoked by banning them. Bans are checked on every request whether or not the token was
+//! cached, and finding one evicts every entry of the banned user or device.
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use snipsnap_lib::database::Subject;
+
+/// Tokens to remember, least recently used first out.
+const DEFAULT_CAPACITY: usize = 1024;
+
+struct Validation {
+    user_id: String,
+    device_id: String,
+    /// The nonce the token was issued for.
+    nonce: String,
+    /// The token's `exp`, in seconds since the epoch.
+    expires_at: i64,
+}
+
+pub struct ValidationCache {
+    entries: Mutex<LruCache<[u8; 32], Validation>>,
+}
+
+impl ValidationCache {
+
+    pub fn new() -> ValidationCache {
+        ValidationCache::with_capacity(NonZeroUsize::new(DEFAULT_CAPACITY).unwrap_or(NonZeroUsize::MIN))
+    }
+
+    pub fn with_capacity(capacity: NonZeroUsize) -> ValidationCache {
+        ValidationCache { entries: Mutex::new(LruCache::new(capacity)) }
+    }
+
+    /// The nonce of `token`, if it was validated for `user_id` on `device_id` and hasn't expired since.
+    pub fn get(&self, token: &str, user_id: &str, device_id: &str) -> Option<String> {
+        let mut entries = self.entries.lock().ok()?;
+        let key = key(token, device_id);
+        match entries.get(&key) {
+            Some(validation) if validation.expires_at <= Utc::now().timestamp() => {
+                entries.pop(&key);
+                None
+            }
+            Some(validation) if validation.user_id == user_id => Some(validation.nonce.clone()),
+            _ => None,
+        }
+    }
+
+    /// Remember that `token`, issued for `nonce`, checked out for `user_id` on `device_id`, until `expires_at`.
+    pub fn insert(&self, token: &str, user_id: &str, device_id: &str, nonce: &str, expires_at: i64) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.put(key(token, device_id), Validation {
+                user_id: String::from(user_id),
+                device_id: String::from(device_id),
+                nonce: String::from(nonce),
+                expires_at,
+            });
+        }
+    }
+
+    /// Forget `token`, e.g. once its nonce no longer matches.
+    pub fn remove(&self, token: &str, device_id: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.pop(&key(token, device_id));
+        }
+    }
+
+    /// Forget every token of a banned user or device.
+    pub fn invalidate(&self, subject: &Subject) {
+        if let Ok(mut entries) = self.entries.lock() {
+            let revoked: Vec<[u8; 32]> = entries.iter()
+                .filter(|(_, validation)| match subject {
+                    Subject::User(user_id) => &validation.user_id == user_id,
+                    Subject::Device(device_id) => &validation.device_id == device_id,
+                })
+                .map(|(key, _)| *key)
+                .collect();
+            for key in revoked {
+                entries.pop(&key);
+            }
+        }
+    }
+}
+
+impl Default for ValidationCache {
+    fn default() -> Self {
+        ValidationCache::new()
+    }
+}
+
+fn key(token: &str, device_id: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    // so the boundary between the two can't move
+    hasher.update([0]);
+    hasher.update(device_id.as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use chrono::Utc;
+    use snipsnap_lib::database::Subject;
+
+    use crate::validation_cache::ValidationCache;
+
+    #[test]
+    fn test_validation_cache() {
+        let cache = ValidationCache::with_capacity(NonZeroUsize::new(2).expect("Zero capacity"));
+        let later = Utc::now().timestamp() + 60;
+        cache.insert("token", "user", "device", "nonce", later);
+        assert_eq!(cache.get("token", "user", "device").as_deref(), Some("nonce"));
+        assert_eq!(cache.get("token", "other", "device"), None, "cached for another user");
+        assert_eq!(cache.get("token", "user", "other"), None, "cached for another device");
+
+        cache.insert("expired", "user", "device", "nonce", Utc::now().timestamp() - 1);
+        assert_eq!(cache.get("expired", "user", "device"), None, "cached past exp");
+
+        cache.insert("other", "other", "device", "nonce", later);
+        cache.invalidate(&Subject::User(String::from("user")));
+        assert_eq!(cache.get("token", "user", "device"), None, "cached after invalidating the user");
+        assert!(cache.get("other", "other", "device").is_some());
+        cache.invalidate(&Subject::Device(String::from("device")));
+        assert_eq!(cache.get("other", "other", "device"), None, "cached after invalidating the device");
+    }
+}
@@ -6,3 +6,8 @@ pub const AUTHORIZATION_HEADER: &str = "Authorization";
 pub const TOKEN_PREFIX: &str = "Bearer ";
 pub const USER_ID_HEADER: &str = "X-UserId";
 pub const DEVICE_ID_HEADER: &str = "X-DeviceId";
+/// The `principalId` of IAM policies for callers without a user, which REST APIs require anyway.
+pub const ANONYMOUS_PRINCIPAL: &str = "anonymous";
+pub const ADMIN_USER_IDS_VARIABLE: &str = "SNIPSNAP_ADMIN_USER_IDS";
+/// The `code` of denials for outdated builds, the same as the error code the handlers answer them with.
+pub const UPGRADE_REQUIRED_CODE: &str = "UPGRADE_REQUIRED";
@@ -2,40 +2,26 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use lambda_http::{Body, Error, Request, Response, run, service_fn};
-use serde::{Deserialize, Serialize};
-use snipsnap_lib::database::NoncesTable;
-use snipsnap_lib::http::{HttpErrorResponse, HttpResponseGenerator};
-
-#[derive(Deserialize)]
-#[allow(non_snake_case)]
-struct GetNonceRequest {
-    deviceId: String,
-}
+use std::sync::Arc;
 
-#[derive(Serialize)]
-struct GetNonceResponse {
-    nonce: String,
-}
+use lambda_http::{Body, Error, Request, Response, run, service_fn};
+use snipsnap_lib::api::{GetNonceRequest, GetNonceResponse};
+use snipsnap_lib::database::{Database, NoncesTable};
+use snipsnap_lib::http::{FromRequest, ApiError, HttpResponseGenerator, Idempotency, Json, Router};
 
-async fn handler(event: Request) -> Result<Response<Body>, Error> {
-    if let Ok(decoded) = String::from_utf8(event.body().to_vec()) {
-        if let Ok(deserialized) = serde_json::from_str::<GetNonceRequest>(&decoded) {
-            return match NoncesTable::make_nonce(&*deserialized.deviceId).await {
-                Ok(nonce) => {
-                    let body = GetNonceResponse { nonce };
-                    HttpResponseGenerator::response(200, &body)
-                },
-                Err(e) => {
-                    let body = HttpErrorResponse::new(format!("Error making nonce: {e}"));
-                    HttpResponseGenerator::response(500, &body)
-                }
-            }
-        }
+async fn handler(database: Arc<Database>, event: Request) -> Result<Response<Body>, ApiError> {
+    let Json(request) = Json::<GetNonceRequest>::from_request(&event)?;
+    match NoncesTable::make_nonce(&database, request.device_id()).await {
+        Ok(nonce) => {
+            let body = GetNonceResponse::new(nonce);
+            Ok(HttpResponseGenerator::response(200, &body))
+        },
+        Err(e) => Err(ApiError::internal("Could not make a nonce").with_diagnostic(e)),
     }
+}
 
-    let body = HttpErrorResponse::new("Error decoding body".to_string());
-    HttpResponseGenerator::response(400, &body)
+fn router(database: Database) -> Router<Database> {
+    Router::new(database).versioned(1, |v| v.post("/get-nonce", handler))
 }
 
 #[tokio::main]
@@ -48,5 +34,52 @@ async fn main() -> Result<(), Error> {
         .without_time()
         .init();
 
-    run(service_fn(handler)).await
+    let database = Database::from_env()?;
+    let router = router(database.clone()).with_idempotency(Idempotency::new(database));
+    let router = &router;
+    run(service_fn(move |event| router.handle(event))).await
+}
+
+#[cfg(test)]
+mod test {
+    use lambda_http::{Body, Request};
+    use snipsnap_lib::database::{Database, DatabaseConfig};
+    use snipsnap_lib::api::{GetNonceRequest, GetNonceResponse};
+    use snipsnap_lib::http::{OpenApiSpec, Strictness};
+
+    use crate::router;
+
+    fn spec() -> OpenApiSpec {
+        OpenApiSpec::from_yaml(include_str!("../../../../openapi.yaml")).expect("Failed to parse openapi.yaml")
+    }
+
+    fn request(content_type: &str, body: &str) -> Request {
+        lambda_http::http::Request::builder()
+            .method("POST")
+            .uri("/get-nonce")
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_invalid_body() {
+        let router = router(Database::new(DatabaseConfig::default().with_stage("test")).expect("Failed to create database"));
+        let response = router.handle(request("application/json", "{}")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 422);
+        assert_eq!(spec().validate_response("POST", "/get-nonce", &response), vec![]);
+        let response = router.handle(request("text/plain", "deviceId")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 415);
+        assert_eq!(spec().validate_response("POST", "/get-nonce", &response), vec![]);
+    }
+
+    #[test]
+    fn test_types_match_spec() {
+        let request = serde_json::to_value(GetNonceRequest::new("device".to_string()))
+            .expect("Failed to serialize request");
+        assert_eq!(spec().validate_schema("GetNonceRequest", &request, Strictness::Strict), vec![]);
+        let response = serde_json::to_value(GetNonceResponse::new("nonce".to_string()))
+            .expect("Failed to serialize response");
+        assert_eq!(spec().validate_schema("GetNonceResponse", &response, Strictness::Strict), vec![]);
+    }
 }
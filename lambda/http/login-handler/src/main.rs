@@ -2,34 +2,36 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
+use std::sync::Arc;
+
 use lambda_http::{Body, Error, Request, Response, run, service_fn};
-use serde::Serialize;
-use snipsnap_lib::database::LoginsTable;
-use snipsnap_lib::http::{HttpErrorResponse, HttpResponseGenerator};
+use snipsnap_lib::api::LoginResponse;
+use snipsnap_lib::database::{Database, LoginsTable};
+use snipsnap_lib::http::{
+    AuthenticatedUser, FromRequest, ApiError, HttpResponseBuilder, Idempotency, Router, SessionTokens,
+};
 
-#[derive(Serialize)]
-struct LoginResponse {
-    message: String,
+/// Shared by every request, created once per process.
+struct Login {
+    database: Database,
+    sessions: SessionTokens,
 }
 
-async fn function_handler(event: Request) -> Result<Response<Body>, Error> {
-    if let Some(user_id_header) = event.headers().get("X-UserId") {
-        if let Ok(user_id) = user_id_header.to_str() {
-            return match LoginsTable::record_login(user_id).await {
-                Ok(_) => {
-                    let body = LoginResponse { message: "Login successful and logged!".to_string() };
-                    HttpResponseGenerator::response(200, &body)
-                },
-                Err(e) => {
-                    let body = HttpErrorResponse::new(format!("Error recording login: {e}"));
-                    HttpResponseGenerator::response(500, &body)
-                }
-            }
-        }
+async fn function_handler(login: Arc<Login>, event: Request) -> Result<Response<Body>, ApiError> {
+    let user = AuthenticatedUser::from_request(&event)?;
+    match LoginsTable::record_login(&login.database, user.user_id()).await {
+        Ok(_) => {
+            let (session_token, expires_at) = login.sessions.issue(user.user_id(), user.device_id());
+            let body = LoginResponse::new("Login successful and logged!".to_string(), session_token, expires_at);
+            // carries the session token, so neither caches nor the idempotency table keep it
+            Ok(HttpResponseBuilder::json(200, &body).cache_control("no-store").build())
+        },
+        Err(e) => Err(ApiError::internal("Could not record the login").with_diagnostic(e)),
     }
+}
 
-    let body = HttpErrorResponse::new("Could not get X-UserId header".to_string());
-    HttpResponseGenerator::response(400, &body)
+fn router(login: Login) -> Router<Login> {
+    Router::new(login).versioned(1, |v| v.post("/login", function_handler))
 }
 
 #[tokio::main]
@@ -42,13 +44,50 @@ async fn main() -> Result<(), Error> {
         .without_time()
         .init();
 
-    run(service_fn(function_handler)).await
+    let database = Database::from_env()?;
+    let login = Login {
+        database: database.clone(),
+        sessions: SessionTokens::from_env()?,
+    };
+    let router = router(login).with_idempotency(Idempotency::new(database));
+    let router = &router;
+    run(service_fn(move |event| router.handle(event))).await
 }
 
 #[cfg(test)]
 mod test {
+    use lambda_http::Body;
+    use snipsnap_lib::database::{Database, DatabaseConfig};
+    use snipsnap_lib::api::LoginResponse;
+    use snipsnap_lib::http::{OpenApiSpec, SessionTokens, Strictness};
+
+    use crate::{Login, router};
+
+    fn spec() -> OpenApiSpec {
+        OpenApiSpec::from_yaml(include_str!("../../../../openapi.yaml")).expect("Failed to parse openapi.yaml")
+    }
+
     #[tokio::test]
-    async fn test() {
-        assert!(true)
+    async fn test_missing_authorizer_context() {
+        let router = router(Login {
+            database: Database::new(DatabaseConfig::default().with_stage("test")).expect("Failed to create database"),
+            sessions: SessionTokens::ephemeral(),
+        });
+        let request = lambda_http::http::Request::builder()
+            .method("POST")
+            .uri("/login")
+            .body(Body::Empty)
+            .expect("Failed to build request");
+        let response = router.handle(request).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 401);
+        assert_eq!(spec().validate_response("POST", "/login", &response), vec![]);
+    }
+
+    #[test]
+    fn test_types_match_spec() {
+        let (session_token, expires_at) = SessionTokens::ephemeral().issue("user", "device");
+        let response = serde_json::to_value(LoginResponse::new("message".to_string(), session_token, expires_at))
+            .expect("Failed to serialize response");
+        assert_eq!(spec().validate_schema("LoginResponse", &response, Strictness::Strict), vec![]);
     }
 }
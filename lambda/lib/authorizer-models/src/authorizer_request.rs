@@ -0,0 +1,38 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use serde::Deserialize;
+
+use crate::{RequestAuthorizerRequest, SimpleAuthorizerRequest, TokenAuthorizerRequest};
+
+/// The input to an authorizer behind any kind of gateway, so one binary can be deployed behind either.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum AuthorizerRequest {
+    /// HTTP API, payload format 2.0.
+    Simple(SimpleAuthorizerRequest),
+    /// REST API `REQUEST` authorizer, or HTTP API payload format 1.0.
+    Request(RequestAuthorizerRequest),
+    /// REST API `TOKEN` authorizer.
+    Token(TokenAuthorizerRequest),
+}
+
+impl AuthorizerRequest {
+
+    /// The ARN of the route or method being called.
+    pub fn resource_arn(&self) -> &str {
+        match self {
+            AuthorizerRequest::Simple(request) => request.route_arn(),
+            AuthorizerRequest::Request(request) => request.method_arn(),
+            AuthorizerRequest::Token(request) => request.method_arn(),
+        }
+    }
+
+    /// Whether the gateway wants an [`IamPolicyResponse`](crate::IamPolicyResponse) rather than a
+    /// [`SimpleAuthorizerResponse`](crate::SimpleAuthorizerResponse). HTTP APIs can be configured either way, this
+    /// assumes simple responses are enabled for payload format 2.0, which only they support.
+    pub fn expects_policy(&self) -> bool {
+        !matches!(self, AuthorizerRequest::Simple(_))
+    }
+}
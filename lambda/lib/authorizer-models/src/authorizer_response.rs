@@ -0,0 +1,46 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{IamPolicyResponse, SimpleAuthorizerResponse};
+
+/// The response of an authorizer, in the format its [`AuthorizerRequest`](crate::AuthorizerRequest) expects.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum AuthorizerResponse {
+    Simple(SimpleAuthorizerResponse),
+    Policy(IamPolicyResponse),
+}
+
+impl AuthorizerResponse {
+
+    pub fn is_authorized(&self) -> bool {
+        match self {
+            AuthorizerResponse::Simple(response) => response.is_authorized(),
+            AuthorizerResponse::Policy(response) => response.is_authorized(),
+        }
+    }
+
+    pub fn context(&self) -> &HashMap<String, String> {
+        match self {
+            AuthorizerResponse::Simple(response) => response.context(),
+            AuthorizerResponse::Policy(response) => response.context(),
+        }
+    }
+}
+
+impl From<SimpleAuthorizerResponse> for AuthorizerResponse {
+    fn from(response: SimpleAuthorizerResponse) -> Self {
+        AuthorizerResponse::Simple(response)
+    }
+}
+
+impl From<IamPolicyResponse> for AuthorizerResponse {
+    fn from(response: IamPolicyResponse) -> Self {
+        AuthorizerResponse::Policy(response)
+    }
+}
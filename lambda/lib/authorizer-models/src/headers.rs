@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// Headers not made of comma separated values, because their values can contain commas themselves.
+const SINGLE_VALUE_HEADERS: [&str; 7] = [
+    "user-agent", "date", "expires", "if-modified-since", "if-unmodified-since", "last-modified", "set-cookie",
+];
+
+/// Request headers, looked up ignoring case.
+///
+/// HTTP API payload format 2.0 lowercases header names and joins repeated headers with commas, while tests and other
+/// gateways may not, so names are compared the way HTTP defines them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "HashMap<String, String>", into = "HashMap<String, String>")]
+pub struct Headers {
+    // keyed by lowercase name
+    headers: HashMap<String, String>,
+}
+
+impl Headers {
+
+    pub fn new() -> Headers {
+        Headers { headers: HashMap::new() }
+    }
+
+    /// The whole value, with repeated headers joined by commas.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// Each of the comma separated values, ignoring commas in quoted strings. Headers whose values can contain commas,
+    /// like `User-Agent`, are not split.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        let name = name.to_ascii_lowercase();
+        let Some(value) = self.headers.get(&name) else {
+            return Vec::new();
+        };
+        if SINGLE_VALUE_HEADERS.contains(&name.as_str()) {
+            return vec![value.as_str()];
+        }
+
+        let mut values = Vec::new();
+        let mut start = 0;
+        let mut quoted = false;
+        let mut escaped = false;
+        for (i, c) in value.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    values.push(value[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        values.push(value[start..].trim());
+        values.retain(|v| !v.is_empty());
+        values
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.headers.contains_key(&name.to_ascii_lowercase())
+    }
+
+    /// Add a value, joining it to any the header already has.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.headers.entry(name.to_ascii_lowercase())
+            .and_modify(|existing| {
+                existing.push(',');
+                existing.push_str(value);
+            })
+            .or_insert_with(|| String::from(value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Lowercase names and their whole values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl From<HashMap<String, String>> for Headers {
+    fn from(map: HashMap<String, String>) -> Self {
+        let mut headers = Headers::new();
+        for (name, value) in &map {
+            headers.append(name, value);
+        }
+        headers
+    }
+}
+
+impl From<Headers> for HashMap<String, String> {
+    fn from(headers: Headers) -> Self {
+        headers.headers
+    }
+}
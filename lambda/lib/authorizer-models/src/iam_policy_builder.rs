@@ -0,0 +1,93 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+
+use crate::{Effect, IamPolicyResponse, PolicyDocument, RouteArn, Statement};
+
+/// How much of the API a statement covers, relative to the route being authorized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceScope {
+    /// Only the route being called.
+    Route,
+    /// Every route of the stage.
+    Stage,
+}
+
+/// Builds an [`IamPolicyResponse`] for the route in a request's `routeArn`.
+///
+/// ```ignore
+/// let response = IamPolicyResponse::builder(user_id, request.route_arn())
+///     .allow(ResourceScope::Stage)
+///     .context(String::from("userId"), user_id)
+///     .cacheable()
+///     .build();
+/// ```
+pub struct IamPolicyBuilder {
+    principal_id: String,
+    route_arn: String,
+    parsed: Option<RouteArn>,
+    statements: Vec<(Effect, ResourceScope)>,
+    context: HashMap<String, String>,
+    cacheable: bool,
+}
+
+impl IamPolicyBuilder {
+
+    pub fn new(principal_id: String, route_arn: &str) -> IamPolicyBuilder {
+        IamPolicyBuilder {
+            principal_id,
+            route_arn: String::from(route_arn),
+            parsed: RouteArn::parse(route_arn),
+            statements: Vec::new(),
+            context: HashMap::new(),
+            cacheable: false,
+        }
+    }
+
+    pub fn allow(mut self, scope: ResourceScope) -> IamPolicyBuilder {
+        self.statements.push((Effect::Allow, scope));
+        self
+    }
+
+    pub fn deny(mut self, scope: ResourceScope) -> IamPolicyBuilder {
+        self.statements.push((Effect::Deny, scope));
+        self
+    }
+
+    pub fn context(mut self, key: String, value: String) -> IamPolicyBuilder {
+        self.context.insert(key, value);
+        self
+    }
+
+    /// Make the policy safe for API Gateway to cache.
+    ///
+    /// The gateway caches a policy by the authorizer's identity source and reuses it for every route called with the
+    /// same identity, so every statement is widened to the whole stage. Only use this when the decision depends on
+    /// nothing but the identity source, and set a TTL on the authorizer to turn caching on.
+    pub fn cacheable(mut self) -> IamPolicyBuilder {
+        self.cacheable = true;
+        self
+    }
+
+    /// A policy without statements denies everything.
+    pub fn build(self) -> IamPolicyResponse {
+        let statements = self.statements.iter()
+            .map(|(effect, scope)| Statement::new(*effect, vec![self.resource(*scope)]))
+            .collect();
+        IamPolicyResponse::new(self.principal_id, PolicyDocument::new(statements), self.context)
+    }
+
+    fn resource(&self, scope: ResourceScope) -> String {
+        let scope = match self.cacheable {
+            true => ResourceScope::Stage,
+            false => scope,
+        };
+        match (scope, &self.parsed) {
+            (ResourceScope::Stage, Some(arn)) => arn.stage_wide(),
+            // not an execute-api ARN, so there is nothing to widen
+            _ => self.route_arn.clone(),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::IamPolicyBuilder;
+
+const POLICY_VERSION: &str = "2012-10-17";
+const INVOKE_ACTION: &str = "execute-api:Invoke";
+
+/// The response of a REST API authorizer, or an HTTP API authorizer without simple responses.
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct IamPolicyResponse {
+    principalId: String,
+    policyDocument: PolicyDocument,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    context: HashMap<String, String>,
+    /// The API key to meter a usage plan against, REST APIs only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usageIdentifierKey: Option<String>,
+}
+
+impl IamPolicyResponse {
+
+    pub fn new(principal_id: String, policy_document: PolicyDocument, context: HashMap<String, String>) -> IamPolicyResponse {
+        IamPolicyResponse {
+            principalId: principal_id,
+            policyDocument: policy_document,
+            context,
+            usageIdentifierKey: None,
+        }
+    }
+
+    /// Allow `principal_id` to invoke `resource`, an execute-api ARN.
+    pub fn allow(principal_id: String, resource: String, context: HashMap<String, String>) -> IamPolicyResponse {
+        IamPolicyResponse::new(principal_id, PolicyDocument::new(vec![Statement::new(Effect::Allow, vec![resource])]), context)
+    }
+
+    /// Deny `principal_id` from invoking `resource`, an execute-api ARN.
+    pub fn deny(principal_id: String, resource: String, context: HashMap<String, String>) -> IamPolicyResponse {
+        IamPolicyResponse::new(principal_id, PolicyDocument::new(vec![Statement::new(Effect::Deny, vec![resource])]), context)
+    }
+
+    /// Build a policy for the route in `route_arn`, see [`IamPolicyBuilder`].
+    pub fn builder(principal_id: String, route_arn: &str) -> IamPolicyBuilder {
+        IamPolicyBuilder::new(principal_id, route_arn)
+    }
+
+    pub fn with_usage_identifier_key(mut self, usage_identifier_key: String) -> IamPolicyResponse {
+        self.usageIdentifierKey = Some(usage_identifier_key);
+        self
+    }
+}
+
+impl IamPolicyResponse {
+
+    pub fn principal_id(&self) -> &str {
+        &self.principalId
+    }
+    pub fn policy_document(&self) -> &PolicyDocument {
+        &self.policyDocument
+    }
+    pub fn context(&self) -> &HashMap<String, String> {
+        &self.context
+    }
+    pub fn usage_identifier_key(&self) -> Option<&str> {
+        self.usageIdentifierKey.as_deref()
+    }
+
+    /// Whether the policy allows something and denies nothing, since a deny always wins.
+    pub fn is_authorized(&self) -> bool {
+        let statements = self.policyDocument.statements();
+        statements.iter().any(|s| s.effect() == Effect::Allow) && statements.iter().all(|s| s.effect() == Effect::Allow)
+    }
+
+    pub fn insert_context_var(&mut self, key: String, value: String) {
+        self.context.insert(key, value);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyDocument {
+    version: String,
+    statement: Vec<Statement>,
+}
+
+impl PolicyDocument {
+
+    pub fn new(statements: Vec<Statement>) -> PolicyDocument {
+        PolicyDocument {
+            version: String::from(POLICY_VERSION),
+            statement: statements,
+        }
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+    pub fn statements(&self) -> &Vec<Statement> {
+        &self.statement
+    }
+}
+
+/// Allows or denies invoking some execute-api resources.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Statement {
+    action: String,
+    effect: Effect,
+    resource: Vec<String>,
+}
+
+impl Statement {
+
+    pub fn new(effect: Effect, resources: Vec<String>) -> Statement {
+        Statement {
+            action: String::from(INVOKE_ACTION),
+            effect,
+            resource: resources,
+        }
+    }
+
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+    pub fn resources(&self) -> &Vec<String> {
+        &self.resource
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
@@ -5,17 +5,40 @@
 
 mod simple_authorizer_response;
 mod simple_authorizer_request;
+mod simple_authorizer_request_builder;
+mod request_authorizer_request;
+mod token_authorizer_request;
+mod iam_policy_response;
+mod iam_policy_builder;
+mod route_arn;
+mod authorizer_request;
+mod authorizer_response;
+mod headers;
+mod request_context;
 
 pub use simple_authorizer_response::SimpleAuthorizerResponse;
 pub use simple_authorizer_request::SimpleAuthorizerRequest;
+pub use simple_authorizer_request_builder::SimpleAuthorizerRequestBuilder;
+pub use request_authorizer_request::RequestAuthorizerRequest;
+pub use token_authorizer_request::TokenAuthorizerRequest;
+pub use iam_policy_response::{Effect, IamPolicyResponse, PolicyDocument, Statement};
+pub use iam_policy_builder::{IamPolicyBuilder, ResourceScope};
+pub use route_arn::RouteArn;
+pub use authorizer_request::AuthorizerRequest;
+pub use authorizer_response::AuthorizerResponse;
+pub use headers::Headers;
+pub use request_context::{HttpContext, RequestContext};
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
     use std::fs::{File, read_to_string};
+    use proptest::collection::{hash_map, vec};
+    use proptest::prelude::*;
     use serde_json::from_reader;
     use serde_json::error::Result;
     use serde_json::Value::{Array, Null};
+    use crate::{AuthorizerRequest, Effect, Headers, IamPolicyResponse, ResourceScope, RouteArn};
     use crate::simple_authorizer_request::SimpleAuthorizerRequest;
     use crate::simple_authorizer_response::SimpleAuthorizerResponse;
 
@@ -36,7 +59,7 @@ mod tests {
         assert_eq!(request.route_arn(), "arn:aws:execute-api:us-east-1:123456789012:abcdef123/test/GET/request", "route arn");
         if let Array(identity_source) = request.identity_source() {
             assert_eq!(identity_source.len(), 2, "identity source length");
-            assert_eq!(identity_source.get(0).expect("Vec error"), "user1", "identity source[0]")
+            assert_eq!(identity_source.first().expect("Vec error"), "user1", "identity source[0]")
         } else {
             panic!("identity source was not an Array Value");
         }
@@ -44,12 +67,25 @@ mod tests {
         assert_eq!(request.raw_path(), "/my/path", "raw path");
         assert_eq!(request.raw_query_string(), "parameter1=value1&parameter1=value2&parameter2=value", "raw query string");
         assert_eq!(request.cookies().len(), 2, "cookies length");
-        assert_eq!(request.cookies().get(0).expect("Vec error"), "cookie1", "cookies[0]");
+        assert_eq!(request.cookies().first().expect("Vec error"), "cookie1", "cookies[0]");
         assert_eq!(request.headers().len(), 3, "headers length");
         assert_eq!(request.headers().get("Header1").expect("HashMap error"), "value1", "headers[Header1]");
         assert_eq!(request.query_string_parameters().len(), 2, "query string parameters length");
         assert_eq!(request.query_string_parameters().get("parameter1").expect("Vec error"), "value1,value2", "query string parameters[parameter1]");
-        // no tests on request context
+        let context = request.request_context();
+        assert_eq!(context.account_id(), "123456789012", "request context account id");
+        assert_eq!(context.api_id(), "api-id", "request context api id");
+        assert_eq!(context.domain_name(), "id.execute-api.us-east-1.amazonaws.com", "request context domain name");
+        assert_eq!(context.http().method(), "POST", "request context http method");
+        assert_eq!(context.http().path(), "/my/path", "request context http path");
+        assert_eq!(context.http().protocol(), "HTTP/1.1", "request context http protocol");
+        assert_eq!(context.http().source_ip(), "IP", "request context http source ip");
+        assert_eq!(context.http().user_agent(), "agent", "request context http user agent");
+        assert_eq!(context.request_id(), "id", "request context request id");
+        assert_eq!(context.route_key(), "$default", "request context route key");
+        assert_eq!(context.stage(), "$default", "request context stage");
+        assert_eq!(context.time(), "12/Mar/2020:19:03:58 +0000", "request context time");
+        assert_eq!(context.time_epoch(), 1583348638390, "request context time epoch");
         assert_eq!(request.path_parameters().len(), 1, "path parameters length");
         assert_eq!(request.path_parameters().get("parameter1").expect("HashMap error"), "value1", "path parameters[parameter1]");
         assert_eq!(request.stage_variables().len(), 2, "stage variables length");
@@ -75,8 +111,11 @@ mod tests {
         assert!(request.cookies().is_empty(), "cookies length");
         assert_eq!(request.headers().len(), 11, "headers length");
         assert_eq!(request.headers().get("accept").expect("HashMap error"), "*/*", "headers[accept]");
+        assert_eq!(request.headers().get("Content-Type").expect("HashMap error"), "application/json", "headers[Content-Type]");
+        assert_eq!(request.headers().get_all("accept-encoding"), vec!["deflate", "gzip"], "headers[accept-encoding]");
         assert!(request.query_string_parameters().is_empty(), "query string parameters length");
-        // no tests on request context
+        assert_eq!(request.request_context().http().source_ip(), "206.189.205.251", "request context http source ip");
+        assert_eq!(request.request_context().request_id(), "YKEEMhpPPHcEPbQ=", "request context request id");
         assert!(request.path_parameters().is_empty(), "path parameters length");
         assert!(request.stage_variables().is_empty(), "stage variables length");
     }
@@ -94,6 +133,188 @@ mod tests {
         assert_eq!(serialized, expected, "Response was not properly serialized");
     }
 
+    #[test]
+    fn test_valid_v1_request() {
+        let result: Result<AuthorizerRequest> = from_reader(File::open("tests/valid_v1_request.json").expect("expected file"));
+        let Ok(AuthorizerRequest::Request(request)) = result else {
+            panic!("payload format 1.0 was not read as a REQUEST authorizer request");
+        };
+
+        assert_eq!(request.version(), Some("1.0"), "version");
+        assert_eq!(request.method_arn(), "arn:aws:execute-api:us-east-1:123456789012:abcdef123/default/$default", "method arn");
+        assert_eq!(request.authorization_token(), Some("user1,123"), "authorization token");
+        assert_eq!(request.http_method(), "GET", "http method");
+        assert_eq!(request.headers().get("HeaderAuth1").expect("HashMap error"), "headerValue1", "headers[HeaderAuth1]");
+        assert!(request.multi_value_headers().is_empty(), "multi value headers length");
+    }
+
+    #[test]
+    fn test_valid_rest_request() {
+        let result: Result<AuthorizerRequest> = from_reader(File::open("tests/valid_rest_request.json").expect("expected file"));
+        let Ok(AuthorizerRequest::Request(request)) = result else {
+            panic!("REST request was not read as a REQUEST authorizer request");
+        };
+
+        assert_eq!(request.version(), None, "version");
+        assert_eq!(request.payload_type(), "REQUEST", "type");
+        assert_eq!(request.resource(), "/request", "resource");
+        assert_eq!(request.multi_value_headers().get("Authorization").expect("HashMap error"), &vec![String::from("Bearer token")]);
+        assert!(request.query_string_parameters().is_empty(), "query string parameters length");
+        assert!(request.stage_variables().is_empty(), "stage variables length");
+    }
+
+    #[test]
+    fn test_valid_token_request() {
+        let result: Result<AuthorizerRequest> = from_reader(File::open("tests/valid_token_request.json").expect("expected file"));
+        let request = result.expect("Error getting request from result");
+        assert!(request.expects_policy());
+        assert_eq!(request.resource_arn(), "arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/GET/request");
+        let AuthorizerRequest::Token(request) = request else {
+            panic!("TOKEN request was not read as a TOKEN authorizer request");
+        };
+        assert_eq!(request.authorization_token(), "Bearer token", "authorization token");
+
+        let result: Result<AuthorizerRequest> = from_reader(File::open("tests/valid_full_request.json").expect("expected file"));
+        assert!(matches!(result, Ok(AuthorizerRequest::Simple(_))), "payload format 2.0 was not read as a simple request");
+    }
+
+    #[test]
+    fn test_valid_policy_response() {
+        let mut context = HashMap::new();
+        context.insert(String::from("exampleKey"), String::from("exampleValue"));
+        let resource = String::from("arn:aws:execute-api:us-west-2:123456789012:ymy8tbxw7b/prod/GET/request");
+        let response = IamPolicyResponse::allow(String::from("user"), resource.clone(), context);
+        assert!(response.is_authorized());
+        let mut serialized = serde_json::to_string(&response).expect("Failed to serialize response");
+        let mut expected = read_to_string("tests/valid_policy_response.json").expect("Expected file");
+        remove_whitespace(&mut serialized);
+        remove_whitespace(&mut expected);
+        assert_eq!(serialized, expected, "Response was not properly serialized");
+
+        assert!(!IamPolicyResponse::deny(String::from("user"), resource, HashMap::new()).is_authorized());
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut map = HashMap::new();
+        map.insert(String::from("X-Forwarded-For"), String::from("1.1.1.1"));
+        map.insert(String::from("x-forwarded-for"), String::from("2.2.2.2"));
+        map.insert(String::from("if-none-match"), String::from(r#""a,b", "c""#));
+        map.insert(String::from("user-agent"), String::from("Mozilla/5.0 (KHTML, like Gecko)"));
+        let headers = Headers::from(map);
+
+        assert_eq!(headers.len(), 3, "headers length");
+        let mut forwarded = headers.get_all("X-FORWARDED-FOR");
+        forwarded.sort_unstable();
+        assert_eq!(forwarded, vec!["1.1.1.1", "2.2.2.2"], "headers[x-forwarded-for]");
+        assert_eq!(headers.get_all("If-None-Match"), vec![r#""a,b""#, r#""c""#], "headers[if-none-match]");
+        assert_eq!(headers.get_all("User-Agent"), vec!["Mozilla/5.0 (KHTML, like Gecko)"], "headers[user-agent]");
+        assert!(headers.get("authorization").is_none(), "headers[authorization]");
+    }
+
+    #[test]
+    fn test_route_arn() {
+        let arn = RouteArn::parse("arn:aws:execute-api:us-west-2:174026058454:7osxrt1c19/$default/POST/test2").expect("Invalid arn");
+        assert_eq!(arn.api_id(), "7osxrt1c19", "api id");
+        assert_eq!(arn.stage(), "$default", "stage");
+        assert_eq!(arn.route(), "POST/test2", "route");
+        assert_eq!(arn.stage_wide(), "arn:aws:execute-api:us-west-2:174026058454:7osxrt1c19/$default/*", "stage wide");
+        assert_eq!(arn.to_string(), "arn:aws:execute-api:us-west-2:174026058454:7osxrt1c19/$default/POST/test2", "display");
+        assert_eq!(RouteArn::parse("arn:aws:s3:::bucket/key"), None);
+    }
+
+    #[test]
+    fn test_policy_builder() {
+        let request: SimpleAuthorizerRequest = from_reader(File::open("tests/valid_short_request.json").expect("expected file"))
+            .expect("Error getting request from result");
+        let route = "arn:aws:execute-api:us-west-2:174026058454:7osxrt1c19/$default/POST/test2";
+        let stage = "arn:aws:execute-api:us-west-2:174026058454:7osxrt1c19/$default/*";
+
+        let response = IamPolicyResponse::builder(String::from("user"), request.route_arn())
+            .allow(ResourceScope::Route)
+            .deny(ResourceScope::Stage)
+            .context(String::from("userId"), String::from("user"))
+            .build();
+        let statements = response.policy_document().statements();
+        assert_eq!(statements.len(), 2, "statements length");
+        assert_eq!(statements[0].effect(), Effect::Allow, "statements[0] effect");
+        assert_eq!(statements[0].resources(), &vec![String::from(route)], "statements[0] resources");
+        assert_eq!(statements[1].resources(), &vec![String::from(stage)], "statements[1] resources");
+        assert!(!response.is_authorized());
+        assert_eq!(response.context().get("userId").expect("HashMap error"), "user", "context[userId]");
+
+        let cached = IamPolicyResponse::builder(String::from("user"), request.route_arn())
+            .allow(ResourceScope::Route)
+            .cacheable()
+            .build();
+        assert_eq!(cached.policy_document().statements()[0].resources(), &vec![String::from(stage)], "cacheable resources");
+        assert!(cached.is_authorized());
+    }
+
+    #[test]
+    fn test_request_builder() {
+        let request = SimpleAuthorizerRequest::builder()
+            .route_key("POST /login")
+            .header("Authorization", "Bearer token")
+            .header("X-Forwarded-For", "1.1.1.1")
+            .header("x-forwarded-for", "2.2.2.2")
+            .query_string_parameter("a", "1")
+            .query_string_parameter("a", "2")
+            .build();
+        assert_eq!(request.route_arn(), "arn:aws:execute-api:us-east-1:123456789012:api-id/$default/POST/login", "route arn");
+        assert_eq!(request.raw_path(), "/login", "raw path");
+        assert_eq!(request.raw_query_string(), "a=1&a=2", "raw query string");
+        assert_eq!(request.query_string_parameters().get("a").expect("HashMap error"), "1,2", "query string parameters[a]");
+        assert_eq!(request.headers().get("authorization"), Some("Bearer token"), "headers[authorization]");
+        assert_eq!(request.headers().get("x-forwarded-for"), Some("1.1.1.1,2.2.2.2"), "headers[x-forwarded-for]");
+        assert_eq!(request.request_context().http().method(), "POST", "request context http method");
+        assert_eq!(request.request_context().route_key(), "POST /login", "request context route key");
+
+        let request = SimpleAuthorizerRequest::builder()
+            .route_key("ANY /friends/{id}")
+            .http("DELETE", "/friends/1")
+            .build();
+        assert_eq!(request.route_arn(), "arn:aws:execute-api:us-east-1:123456789012:api-id/$default/DELETE/friends/1", "route arn");
+    }
+
+    proptest! {
+        #[test]
+        fn test_request_round_trip(
+            route_key in "(GET|POST|PUT|DELETE|ANY) /[a-z0-9/{}-]{0,20}",
+            headers in vec(("[A-Za-z][A-Za-z0-9-]{0,15}", "[ -~]{0,30}"), 0..8),
+            parameters in vec(("[a-z]{1,8}", "[a-z0-9]{0,8}"), 0..4),
+            cookies in vec("[a-z]{1,8}=[a-z0-9]{0,8}", 0..3),
+            identity_source in vec("[ -~]{1,20}", 0..3),
+        ) {
+            let mut builder = SimpleAuthorizerRequest::builder().route_key(&route_key);
+            for (name, value) in &headers {
+                builder = builder.header(name, value);
+            }
+            for (name, value) in &parameters {
+                builder = builder.query_string_parameter(name, value).path_parameter(name, value);
+            }
+            for cookie in &cookies {
+                builder = builder.cookie(cookie);
+            }
+            if !identity_source.is_empty() {
+                builder = builder.identity_source(&identity_source.iter().map(String::as_str).collect::<Vec<_>>());
+            }
+            let request = builder.build();
+
+            let serialized = serde_json::to_string(&request).expect("Failed to serialize request");
+            let deserialized: SimpleAuthorizerRequest = serde_json::from_str(&serialized).expect("Failed to deserialize request");
+            prop_assert_eq!(deserialized, request);
+        }
+
+        #[test]
+        fn test_response_round_trip(is_authorized: bool, context in hash_map("[A-Za-z]{1,10}", ".{0,20}", 0..5)) {
+            let response = SimpleAuthorizerResponse::new(is_authorized, context);
+            let serialized = serde_json::to_string(&response).expect("Failed to serialize response");
+            let deserialized: SimpleAuthorizerResponse = serde_json::from_str(&serialized).expect("Failed to deserialize response");
+            prop_assert_eq!(deserialized, response);
+        }
+    }
+
     fn remove_whitespace(s: &mut String) {
         s.retain(|c| !c.is_whitespace());
     }
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::Headers;
+
+/// The input to a REST API `REQUEST` authorizer, which is also HTTP API payload format 1.0.
+///
+/// Only the HTTP API sends `version`, `identitySource` and `authorizationToken`. REST sends `null` rather than an
+/// empty map for missing parameters, which is read as empty.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct RequestAuthorizerRequest {
+
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(rename = "type")]
+    payload_type: String,
+    methodArn: String,
+    #[serde(default)]
+    identitySource: Value,
+    #[serde(default)]
+    authorizationToken: Option<String>,
+    resource: String,
+    path: String,
+    httpMethod: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    headers: Headers,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    multiValueHeaders: HashMap<String, Vec<String>>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    queryStringParameters: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    multiValueQueryStringParameters: HashMap<String, Vec<String>>,
+    // would not expect this to be missing
+    requestContext: Map<String, Value>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pathParameters: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    stageVariables: HashMap<String, String>,
+}
+
+impl RequestAuthorizerRequest {
+
+    /// `1.0` from an HTTP API, `None` from a REST API.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+    pub fn payload_type(&self) -> &str {
+        &self.payload_type
+    }
+    pub fn method_arn(&self) -> &str {
+        &self.methodArn
+    }
+    pub fn identity_source(&self) -> &Value {
+        &self.identitySource
+    }
+    pub fn authorization_token(&self) -> Option<&str> {
+        self.authorizationToken.as_deref()
+    }
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn http_method(&self) -> &str {
+        &self.httpMethod
+    }
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+    pub fn multi_value_headers(&self) -> &HashMap<String, Vec<String>> {
+        &self.multiValueHeaders
+    }
+    pub fn query_string_parameters(&self) -> &HashMap<String, String> {
+        &self.queryStringParameters
+    }
+    pub fn multi_value_query_string_parameters(&self) -> &HashMap<String, Vec<String>> {
+        &self.multiValueQueryStringParameters
+    }
+    pub fn request_context(&self) -> &Map<String, Value> {
+        &self.requestContext
+    }
+    pub fn path_parameters(&self) -> &HashMap<String, String> {
+        &self.pathParameters
+    }
+    pub fn stage_variables(&self) -> &HashMap<String, String> {
+        &self.stageVariables
+    }
+}
+
+fn null_as_empty<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>,
+          T: Default + Deserialize<'de>
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
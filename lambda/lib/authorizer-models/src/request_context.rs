@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use serde::{Deserialize, Serialize};
+
+/// The `requestContext` of an HTTP API payload format 2.0 request, describing the call being authorized.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct RequestContext {
+    pub(crate) accountId: String,
+    pub(crate) apiId: String,
+    pub(crate) domainName: String,
+    #[serde(default)]
+    pub(crate) domainPrefix: String,
+    pub(crate) http: HttpContext,
+    pub(crate) requestId: String,
+    pub(crate) routeKey: String,
+    pub(crate) stage: String,
+    /// e.g. `08/Sep/2022:20:15:09 +0000`
+    pub(crate) time: String,
+    /// Milliseconds since the epoch.
+    pub(crate) timeEpoch: i64,
+}
+
+impl RequestContext {
+
+    pub fn account_id(&self) -> &str {
+        &self.accountId
+    }
+    pub fn api_id(&self) -> &str {
+        &self.apiId
+    }
+    pub fn domain_name(&self) -> &str {
+        &self.domainName
+    }
+    pub fn domain_prefix(&self) -> &str {
+        &self.domainPrefix
+    }
+    pub fn http(&self) -> &HttpContext {
+        &self.http
+    }
+    /// The API Gateway request id, which is also in the access logs.
+    pub fn request_id(&self) -> &str {
+        &self.requestId
+    }
+    pub fn route_key(&self) -> &str {
+        &self.routeKey
+    }
+    pub fn stage(&self) -> &str {
+        &self.stage
+    }
+    pub fn time(&self) -> &str {
+        &self.time
+    }
+    pub fn time_epoch(&self) -> i64 {
+        self.timeEpoch
+    }
+}
+
+/// The HTTP request behind a [`RequestContext`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct HttpContext {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) protocol: String,
+    pub(crate) sourceIp: String,
+    pub(crate) userAgent: String,
+}
+
+impl HttpContext {
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+    /// The address of the client, or of the last proxy before API Gateway.
+    pub fn source_ip(&self) -> &str {
+        &self.sourceIp
+    }
+    pub fn user_agent(&self) -> &str {
+        &self.userAgent
+    }
+}
@@ -0,0 +1,58 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::fmt::{Display, Formatter};
+
+/// An execute-api ARN, `arn:aws:execute-api:{region}:{accountId}:{apiId}/{stage}/{method}/{path}`, as in
+/// [`SimpleAuthorizerRequest::route_arn`](crate::SimpleAuthorizerRequest::route_arn).
+///
+/// HTTP APIs put the whole route after the stage, e.g. `$default/POST/login`, and `$default` routes have no method.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteArn {
+    prefix: String,
+    api_id: String,
+    stage: String,
+    route: String,
+}
+
+impl RouteArn {
+
+    pub fn parse(arn: &str) -> Option<RouteArn> {
+        let (prefix, resource) = arn.rsplit_once(':')?;
+        if !prefix.starts_with("arn:") || !prefix.contains(":execute-api:") {
+            return None;
+        }
+        let mut parts = resource.splitn(3, '/');
+        let api_id = parts.next().filter(|p| !p.is_empty())?;
+        let stage = parts.next().filter(|p| !p.is_empty())?;
+        Some(RouteArn {
+            prefix: String::from(prefix),
+            api_id: String::from(api_id),
+            stage: String::from(stage),
+            route: String::from(parts.next().unwrap_or_default()),
+        })
+    }
+
+    pub fn api_id(&self) -> &str {
+        &self.api_id
+    }
+    pub fn stage(&self) -> &str {
+        &self.stage
+    }
+    /// The method and path, e.g. `POST/login`.
+    pub fn route(&self) -> &str {
+        &self.route
+    }
+
+    /// Every route of the stage.
+    pub fn stage_wide(&self) -> String {
+        format!("{}:{}/{}/*", self.prefix, self.api_id, self.stage)
+    }
+}
+
+impl Display for RouteArn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}/{}/{}", self.prefix, self.api_id, self.stage, self.route)
+    }
+}
@@ -3,37 +3,44 @@
  */
 
 use std::collections::HashMap;
-use serde::Deserialize;
-use serde_json::{Map, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Deserialize)]
+use crate::{Headers, RequestContext, SimpleAuthorizerRequestBuilder};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[allow(non_snake_case)]
 pub struct SimpleAuthorizerRequest {
 
-    version: String,
+    pub(crate) version: String,
     #[serde(rename = "type")]
-    payload_type: String,
-    routeArn: String,
-    identitySource: Value,
-    routeKey: String,
-    rawPath: String,
-    rawQueryString: String,
+    pub(crate) payload_type: String,
+    pub(crate) routeArn: String,
+    pub(crate) identitySource: Value,
+    pub(crate) routeKey: String,
+    pub(crate) rawPath: String,
+    pub(crate) rawQueryString: String,
     #[serde(default = "Vec::new")]
-    cookies: Vec<String>,
-    #[serde(default = "HashMap::new")]
-    headers: HashMap<String, String>,
+    pub(crate) cookies: Vec<String>,
+    #[serde(default)]
+    pub(crate) headers: Headers,
     #[serde(default = "HashMap::new")]
-    queryStringParameters: HashMap<String, String>,
+    pub(crate) queryStringParameters: HashMap<String, String>,
     // would not expect this to be missing
-    requestContext: Map<String, Value>,
+    pub(crate) requestContext: RequestContext,
     #[serde(default = "HashMap::new")]
-    pathParameters: HashMap<String, String>,
+    pub(crate) pathParameters: HashMap<String, String>,
     #[serde(default = "HashMap::new")]
-    stageVariables: HashMap<String, String>,
+    pub(crate) stageVariables: HashMap<String, String>,
 }
 
 impl SimpleAuthorizerRequest {
 
+    /// A request to build up for tests and tools, see [`SimpleAuthorizerRequestBuilder`].
+    pub fn builder() -> SimpleAuthorizerRequestBuilder {
+        SimpleAuthorizerRequestBuilder::new()
+    }
+
     pub fn version(&self) -> &str {
         &self.version
     }
@@ -58,13 +65,13 @@ impl SimpleAuthorizerRequest {
     pub fn cookies(&self) -> &Vec<String> {
         &self.cookies
     }
-    pub fn headers(&self) -> &HashMap<String, String> {
+    pub fn headers(&self) -> &Headers {
         &self.headers
     }
     pub fn query_string_parameters(&self) -> &HashMap<String, String> {
         &self.queryStringParameters
     }
-    pub fn request_context(&self) -> &Map<String, Value> {
+    pub fn request_context(&self) -> &RequestContext {
         &self.requestContext
     }
     pub fn path_parameters(&self) -> &HashMap<String, String> {
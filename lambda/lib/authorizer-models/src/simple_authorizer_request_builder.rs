@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::{Headers, HttpContext, RequestContext, SimpleAuthorizerRequest};
+
+const ACCOUNT_ID: &str = "123456789012";
+const API_ID: &str = "api-id";
+const REGION: &str = "us-east-1";
+const DEFAULT_ROUTE: &str = "$default";
+
+/// Builds a [`SimpleAuthorizerRequest`] for tests and tools, filling in everything not set like API Gateway would for
+/// a request to the `$default` stage.
+///
+/// ```ignore
+/// let request = SimpleAuthorizerRequest::builder()
+///     .route_key("POST /login")
+///     .header("Authorization", "Bearer token")
+///     .build();
+/// ```
+pub struct SimpleAuthorizerRequestBuilder {
+    route_key: String,
+    method: String,
+    path: String,
+    stage: String,
+    headers: Headers,
+    query_string_parameters: Vec<(String, String)>,
+    cookies: Vec<String>,
+    identity_source: Value,
+    path_parameters: HashMap<String, String>,
+    stage_variables: HashMap<String, String>,
+    source_ip: String,
+    request_id: String,
+}
+
+impl SimpleAuthorizerRequestBuilder {
+
+    pub fn new() -> SimpleAuthorizerRequestBuilder {
+        SimpleAuthorizerRequestBuilder {
+            route_key: String::from(DEFAULT_ROUTE),
+            method: String::from("GET"),
+            path: String::from("/"),
+            stage: String::from(DEFAULT_ROUTE),
+            headers: Headers::new(),
+            query_string_parameters: Vec::new(),
+            cookies: Vec::new(),
+            identity_source: Value::Null,
+            path_parameters: HashMap::new(),
+            stage_variables: HashMap::new(),
+            source_ip: String::from("127.0.0.1"),
+            request_id: String::from("request-id"),
+        }
+    }
+
+    /// e.g. `POST /login`, which is also used as the method and path unless the path has parameters.
+    pub fn route_key(mut self, route_key: &str) -> SimpleAuthorizerRequestBuilder {
+        if let Some((method, path)) = route_key.split_once(' ') {
+            if method != "ANY" {
+                self.method = String::from(method);
+            }
+            if !path.contains('{') {
+                self.path = String::from(path);
+            }
+        }
+        self.route_key = String::from(route_key);
+        self
+    }
+
+    /// The method and path actually called, for routes with parameters or `ANY` methods.
+    pub fn http(mut self, method: &str, path: &str) -> SimpleAuthorizerRequestBuilder {
+        self.method = String::from(method);
+        self.path = String::from(path);
+        self
+    }
+
+    pub fn stage(mut self, stage: &str) -> SimpleAuthorizerRequestBuilder {
+        self.stage = String::from(stage);
+        self
+    }
+
+    /// Add a header, joining repeated ones with commas like API Gateway does.
+    pub fn header(mut self, name: &str, value: &str) -> SimpleAuthorizerRequestBuilder {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn query_string_parameter(mut self, name: &str, value: &str) -> SimpleAuthorizerRequestBuilder {
+        self.query_string_parameters.push((String::from(name), String::from(value)));
+        self
+    }
+
+    pub fn cookie(mut self, cookie: &str) -> SimpleAuthorizerRequestBuilder {
+        self.cookies.push(String::from(cookie));
+        self
+    }
+
+    /// The values of the authorizer's identity sources, which API Gateway sends as a list.
+    pub fn identity_source(mut self, values: &[&str]) -> SimpleAuthorizerRequestBuilder {
+        self.identity_source = Value::from(values.to_vec());
+        self
+    }
+
+    pub fn path_parameter(mut self, name: &str, value: &str) -> SimpleAuthorizerRequestBuilder {
+        self.path_parameters.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn stage_variable(mut self, name: &str, value: &str) -> SimpleAuthorizerRequestBuilder {
+        self.stage_variables.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn source_ip(mut self, source_ip: &str) -> SimpleAuthorizerRequestBuilder {
+        self.source_ip = String::from(source_ip);
+        self
+    }
+
+    /// The API Gateway request id.
+    pub fn request_id(mut self, request_id: &str) -> SimpleAuthorizerRequestBuilder {
+        self.request_id = String::from(request_id);
+        self
+    }
+
+    pub fn build(self) -> SimpleAuthorizerRequest {
+        let route = match self.route_key.as_str() {
+            DEFAULT_ROUTE => String::from(DEFAULT_ROUTE),
+            _ => format!("{}{}", self.method, self.path),
+        };
+        let domain_name = format!("{API_ID}.execute-api.{REGION}.amazonaws.com");
+
+        // repeated parameters are joined with commas, like headers
+        let mut query_string_parameters: HashMap<String, String> = HashMap::new();
+        for (name, value) in &self.query_string_parameters {
+            query_string_parameters.entry(name.clone())
+                .and_modify(|existing| {
+                    existing.push(',');
+                    existing.push_str(value);
+                })
+                .or_insert_with(|| value.clone());
+        }
+        let raw_query_string = self.query_string_parameters.iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let user_agent = String::from(self.headers.get("user-agent").unwrap_or_default());
+
+        SimpleAuthorizerRequest {
+            version: String::from("2.0"),
+            payload_type: String::from("REQUEST"),
+            routeArn: format!("arn:aws:execute-api:{REGION}:{ACCOUNT_ID}:{API_ID}/{}/{route}", self.stage),
+            identitySource: self.identity_source,
+            routeKey: self.route_key.clone(),
+            rawPath: self.path.clone(),
+            rawQueryString: raw_query_string,
+            cookies: self.cookies,
+            headers: self.headers,
+            queryStringParameters: query_string_parameters,
+            requestContext: RequestContext {
+                accountId: String::from(ACCOUNT_ID),
+                apiId: String::from(API_ID),
+                domainName: domain_name,
+                domainPrefix: String::from(API_ID),
+                http: HttpContext {
+                    method: self.method,
+                    path: self.path,
+                    protocol: String::from("HTTP/1.1"),
+                    sourceIp: self.source_ip,
+                    userAgent: user_agent,
+                },
+                requestId: self.request_id,
+                routeKey: self.route_key,
+                stage: self.stage,
+                time: String::from("01/Jan/1970:00:00:00 +0000"),
+                timeEpoch: 0,
+            },
+            pathParameters: self.path_parameters,
+            stageVariables: self.stage_variables,
+        }
+    }
+}
+
+impl Default for SimpleAuthorizerRequestBuilder {
+    fn default() -> Self {
+        SimpleAuthorizerRequestBuilder::new()
+    }
+}
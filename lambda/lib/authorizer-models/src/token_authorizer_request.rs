@@ -0,0 +1,29 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use serde::Deserialize;
+
+/// The input to a REST API `TOKEN` authorizer, which only sees the configured token header.
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct TokenAuthorizerRequest {
+
+    #[serde(rename = "type")]
+    payload_type: String,
+    authorizationToken: String,
+    methodArn: String,
+}
+
+impl TokenAuthorizerRequest {
+
+    pub fn payload_type(&self) -> &str {
+        &self.payload_type
+    }
+    pub fn authorization_token(&self) -> &str {
+        &self.authorizationToken
+    }
+    pub fn method_arn(&self) -> &str {
+        &self.methodArn
+    }
+}
@@ -14,22 +14,22 @@
 
 mod data;
 mod error;
+mod validator;
 
-pub use data::{Claims, ClaimsServer2Server};
+pub use data::{Claims, ClaimsServer2Server, KeyComponents};
 pub use error::Error;
+pub use validator::{KeySource, Nonces, Validator};
 
-use data::{KeyComponents, APPLE_ISSUER, APPLE_PUB_KEYS};
+use data::APPLE_PUB_KEYS;
 use error::Result;
 use hyper::{body, Body, Client, Request};
 use hyper_tls::HttpsConnector;
-use jsonwebtoken::{
-	self, decode, decode_header, DecodingKey, TokenData, Validation,
-};
+use jsonwebtoken::{self, TokenData};
 use serde::de::DeserializeOwned;
+use snipsnap_lib::database::Database;
 use std::collections::HashMap;
-use snipsnap_lib::database::nonces_table::NoncesTable;
 
-//TODO: put verification into a struct and only fetch apple keys once in the beginning
+//TODO: only fetch apple keys once in the beginning
 async fn fetch_apple_keys() -> Result<HashMap<String, KeyComponents>>
 {
 	let https = HttpsConnector::new();
@@ -59,65 +59,22 @@ pub async fn decode_token<T: DeserializeOwned>(
 	token: String,
 	ignore_expire: bool,
 ) -> Result<TokenData<T>> {
-	let header = decode_header(token.as_str())?;
-
-	let kid = match header.kid {
-		Some(k) => k,
-		None => return Err(Error::KidNotFound),
-	};
-
-	let pubkeys = fetch_apple_keys().await?;
-
-	let pubkey = match pubkeys.get(&kid) {
-		Some(key) => key,
-		None => return Err(Error::KeyNotFound),
-	};
-
-	let mut val = Validation::new(header.alg);
-	val.validate_exp = !ignore_expire;
-	let token_data = decode::<T>(
-		token.as_str(),
-		&DecodingKey::from_rsa_components(&pubkey.n, &pubkey.e)
-			.unwrap(),
-		&val,
-	)?;
-
-	Ok(token_data)
+	// decoding doesn't look up nonces
+	Validator::new(KeySource::Apple, Nonces::memory(HashMap::new()))
+		.decode_token(&token, ignore_expire)
+		.await
 }
 
 pub async fn validate(
+	database: &Database,
 	client_id: String,
 	token: String,
 	device_id: String,
 	ignore_expire: bool,
 ) -> Result<TokenData<Claims>> {
-
-	// TODO add nonce validation here
-	let nonces_table = NoncesTable { };
-
-	let token_data = decode_token::<Claims>(token, ignore_expire).await?;
-
-	//TODO: can this be validated alread in `decode_token`?
-	if token_data.claims.iss != APPLE_ISSUER {
-		return Err(Error::IssClaimMismatch);
-	}
-
-	if token_data.claims.sub != client_id {
-		return Err(Error::ClientIdMismatch);
-	}
-
-	match NoncesTable::get_nonce(&*device_id).await {
-		Ok(str) => {
-			if str != token_data.claims.aud {
-				return Err(Error::NonceMistmatch)
-			}
-		},
-		Err(e) => {
-			// TODO match e and return an appropriate error
-		}
-	}
-
-	Ok(token_data)
+	Validator::apple(database.clone())
+		.validate(&client_id, &token, &device_id, ignore_expire)
+		.await
 }
 
 /// allows to check whether the `validate` result was errored because of an expired signature
@@ -140,7 +97,6 @@ pub fn is_expired(
 mod tests {
 	use crate::{
 		decode_token, /*is_expired, validate,*/ ClaimsServer2Server,
-		Error,
 	};
 
 	// #[tokio::test]
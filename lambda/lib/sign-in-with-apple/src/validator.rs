@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Validation against swappable key and nonce sources, so tokens can
+//! be checked locally without Apple or the database.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use jsonwebtoken::{
+	decode, decode_header, DecodingKey, TokenData, Validation,
+};
+use serde::de::DeserializeOwned;
+use snipsnap_lib::database::{Database, NoncesTable};
+
+use crate::data::{KeyComponents, APPLE_ISSUER};
+use crate::error::{Error, Result};
+use crate::{fetch_apple_keys, Claims};
+
+/// Where the public keys tokens are signed with come from.
+pub enum KeySource {
+	/// Fetched from Apple for every token.
+	Apple,
+	/// A fixed set of keys, by key id.
+	Local(HashMap<String, KeyComponents>),
+}
+
+impl KeySource {
+	/// Keys from a JWKS document, shaped like Apple's
+	/// `{"keys": [...]}`.
+	pub fn from_jwks(json: &[u8]) -> Result<Self> {
+		let mut jwks: HashMap<String, Vec<KeyComponents>> =
+			serde_json::from_slice(json)?;
+		let keys = jwks.remove("keys").ok_or(Error::AppleKeys)?;
+		Ok(Self::Local(
+			keys.into_iter()
+				.map(|key| (key.kid.clone(), key))
+				.collect(),
+		))
+	}
+
+	async fn key(&self, kid: &str) -> Result<DecodingKey> {
+		let fetched;
+		let keys = match self {
+			Self::Apple => {
+				fetched = fetch_apple_keys().await?;
+				&fetched
+			}
+			Self::Local(keys) => keys,
+		};
+		let key = keys.get(kid).ok_or(Error::KeyNotFound)?;
+		Ok(DecodingKey::from_rsa_components(&key.n, &key.e)?)
+	}
+}
+
+/// Where the nonces apps got from `/get-nonce` are looked up. A nonce
+/// is used up by looking it up.
+pub enum Nonces {
+	Database(Database),
+	/// Device ids to nonces, for running locally.
+	Memory(Mutex<HashMap<String, String>>),
+}
+
+impl Nonces {
+	#[must_use]
+	pub const fn memory(nonces: HashMap<String, String>) -> Self {
+		Self::Memory(Mutex::new(nonces))
+	}
+
+	async fn take(&self, device_id: &str) -> Option<String> {
+		match self {
+			Self::Database(database) => {
+				// TODO match the error and return an appropriate one
+				NoncesTable::get_nonce(database, device_id).await.ok()
+			}
+			Self::Memory(nonces) => nonces
+				.lock()
+				.map_or(None, |mut nonces| nonces.remove(device_id)),
+		}
+	}
+}
+
+/// Validates Sign In With Apple id tokens.
+pub struct Validator {
+	keys: KeySource,
+	nonces: Nonces,
+}
+
+impl Validator {
+	#[must_use]
+	pub const fn new(keys: KeySource, nonces: Nonces) -> Self {
+		Self { keys, nonces }
+	}
+
+	/// Apple's keys and the nonces in `database`.
+	#[must_use]
+	pub const fn apple(database: Database) -> Self {
+		Self::new(KeySource::Apple, Nonces::Database(database))
+	}
+
+	/// decode token with optional expiry validation
+	pub async fn decode_token<T: DeserializeOwned>(
+		&self,
+		token: &str,
+		ignore_expire: bool,
+	) -> Result<TokenData<T>> {
+		let header = decode_header(token)?;
+
+		let Some(kid) = header.kid else {
+			return Err(Error::KidNotFound);
+		};
+		let key = self.keys.key(&kid).await?;
+
+		let mut val = Validation::new(header.alg);
+		val.validate_exp = !ignore_expire;
+		Ok(decode::<T>(token, &key, &val)?)
+	}
+
+	pub async fn validate(
+		&self,
+		client_id: &str,
+		token: &str,
+		device_id: &str,
+		ignore_expire: bool,
+	) -> Result<TokenData<Claims>> {
+		let token_data =
+			self.decode_token::<Claims>(token, ignore_expire).await?;
+
+		//TODO: can this be validated alread in `decode_token`?
+		if token_data.claims.iss != APPLE_ISSUER {
+			return Err(Error::IssClaimMismatch);
+		}
+
+		if token_data.claims.sub != client_id {
+			return Err(Error::ClientIdMismatch);
+		}
+
+		self.check_nonce(device_id, &token_data.claims.aud).await?;
+
+		Ok(token_data)
+	}
+
+	/// Use up the nonce `device_id` got from `/get-nonce`, which has to
+	/// be `nonce` if it has one.
+	pub async fn check_nonce(&self, device_id: &str, nonce: &str) -> Result<()> {
+		match self.nonces.take(device_id).await {
+			Some(expected) if expected != nonce => Err(Error::NonceMistmatch),
+			_ => Ok(()),
+		}
+	}
+}
@@ -0,0 +1,9 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+pub mod models;
+pub mod specification;
+
+pub use models::{GetNonceRequest, GetNonceResponse, LoginResponse, MessageResponse};
+pub use specification::specification;
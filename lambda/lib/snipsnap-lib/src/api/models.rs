@@ -0,0 +1,74 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /get-nonce`.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[allow(non_snake_case)]
+pub struct GetNonceRequest {
+    deviceId: String,
+}
+
+impl GetNonceRequest {
+    pub fn new(device_id: String) -> GetNonceRequest {
+        GetNonceRequest { deviceId: device_id }
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.deviceId
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetNonceResponse {
+    nonce: String,
+}
+
+impl GetNonceResponse {
+    pub fn new(nonce: String) -> GetNonceResponse {
+        GetNonceResponse { nonce }
+    }
+
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+}
+
+/// Body of `POST /login`, with the session token to send as `Authorization: Bearer <token>` from now on.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[allow(non_snake_case)]
+pub struct LoginResponse {
+    message: String,
+    sessionToken: String,
+    expiresAt: DateTime<Utc>,
+}
+
+impl LoginResponse {
+    pub fn new(message: String, session_token: String, expires_at: DateTime<Utc>) -> LoginResponse {
+        LoginResponse {
+            message,
+            sessionToken: session_token,
+            expiresAt: expires_at,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    pub fn session_token(&self) -> &str {
+        &self.sessionToken
+    }
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expiresAt
+    }
+}
+
+/// What API Gateway itself responds with, e.g. when the authorizer denies a request.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MessageResponse {
+    message: String,
+}
@@ -0,0 +1,202 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! `openapi.yaml`, generated from the request and response types so the two can't drift apart. Regenerate it with
+//! `snipsnap-admin openapi --output ../../../openapi.yaml`.
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::api::{GetNonceRequest, GetNonceResponse, LoginResponse, MessageResponse};
+use crate::http::HttpErrorResponse;
+
+/// For `/login`, which needs the Apple id token along with the user and device it was issued for.
+const APPLE_ID_TOKEN_AUTHORIZER: &str = "signInWithApple";
+/// For every route taking a session token, which is only sent as `Authorization`. API Gateway rejects requests
+/// missing any identity source before asking the authorizer, so these routes can't share the Apple id token's.
+const SESSION_TOKEN_AUTHORIZER: &str = "sessionToken";
+const ERROR_RESPONSE: &str = "ErrorResponse";
+
+/// The whole OpenAPI document.
+pub fn specification() -> Value {
+    let mut generator = SchemaGenerator::new(SchemaSettings::openapi3());
+    let get_nonce_request = reference::<GetNonceRequest>(&mut generator);
+    let get_nonce_response = reference::<GetNonceResponse>(&mut generator);
+    let login_response = reference::<LoginResponse>(&mut generator);
+    let message_response = reference::<MessageResponse>(&mut generator);
+    reference::<HttpErrorResponse>(&mut generator);
+
+    let schemas: Map<String, Value> = generator.take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "SnipSnap",
+            "description": "This is a snapchat replica with basic features. Accounts are integrated with [Sign In With Apple](https://developer.apple.com/sign-in-with-apple/). Users can add and manage friends, and block and report users.",
+            "contact": {
+                "email": "snipsnap.app.contact@gmail.com",
+            },
+            "version": "1.0.0",
+        },
+        "servers": [
+            { "url": "https://7osxrt1c19.execute-api.us-west-2.amazonaws.com/" },
+        ],
+        "tags": [
+            { "name": "login", "description": "login using Sign In With Apple" },
+        ],
+        "paths": {
+            "/get-nonce": {
+                "post": {
+                    "tags": ["login"],
+                    "summary": "Give a nonce to a user for use with Sign In With Apple",
+                    "description": "Take a device id, set a nonce value in dynamodb, and return that value",
+                    "operationId": "getNonce",
+                    "requestBody": {
+                        "description": "Get a nonce for a user to use with Sign In With Apple",
+                        "content": json_content(get_nonce_request),
+                        "required": true,
+                    },
+                    "parameters": [idempotency_key()],
+                    "responses": responses(get_nonce_response, &[
+                        (400, "Client Error"),
+                        (409, "Idempotency-Key was used for a different request, or the first request is still running"),
+                        (415, "Body is not JSON"),
+                        (422, "Body does not match GetNonceRequest"),
+                        (426, "The app must be updated"),
+                        (500, "Server Error"),
+                    ]),
+                },
+            },
+            "/login": {
+                "post": {
+                    "tags": ["login"],
+                    "summary": "Log in using Sign In With Apple",
+                    "operationId": "login",
+                    "security": [{ APPLE_ID_TOKEN_AUTHORIZER: [] }],
+                    "parameters": [
+                        header("X-UserId", "The Sign In With Apple userId"),
+                        header("Authorization", "The Sign In With Apple token issued to the client by Apple, as `Bearer <token>`"),
+                        header("X-DeviceId", "The id of the device used to validate nonce"),
+                        idempotency_key(),
+                    ],
+                    "responses": with_unauthorized(responses(login_response, &[
+                        (400, "Client Error"),
+                        (409, "Idempotency-Key was used for a different request, or the first request is still running"),
+                        (426, "The app must be updated"),
+                        (500, "Server Error"),
+                    ]), message_response),
+                },
+            },
+        },
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                APPLE_ID_TOKEN_AUTHORIZER: {
+                    "type": "apiKey",
+                    "name": "Authorization",
+                    "in": "header",
+                    "description": "Lambda authorizer validating the Sign In With Apple token against the X-UserId and X-DeviceId headers",
+                    "x-amazon-apigateway-authorizer": {
+                        "type": "request",
+                        "identitySource": "$request.header.Authorization, $request.header.X-UserId, $request.header.X-DeviceId",
+                        "authorizerPayloadFormatVersion": "2.0",
+                        "enableSimpleResponses": true,
+                    },
+                },
+                SESSION_TOKEN_AUTHORIZER: {
+                    "type": "apiKey",
+                    "name": "Authorization",
+                    "in": "header",
+                    "description": "The same lambda authorizer, validating a session token issued by /login",
+                    "x-amazon-apigateway-authorizer": {
+                        "type": "request",
+                        "identitySource": "$request.header.Authorization",
+                        "authorizerPayloadFormatVersion": "2.0",
+                        "enableSimpleResponses": true,
+                    },
+                },
+            },
+        },
+    })
+}
+
+fn reference<T: JsonSchema>(generator: &mut SchemaGenerator) -> Value {
+    serde_json::to_value(generator.subschema_for::<T>()).unwrap_or_default()
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn error_reference() -> Value {
+    json!({ "$ref": format!("#/components/schemas/{ERROR_RESPONSE}") })
+}
+
+fn responses(success: Value, errors: &[(u16, &str)]) -> Value {
+    let mut responses = Map::new();
+    responses.insert("200".to_string(), json!({
+        "description": "Successful operation",
+        "content": json_content(success),
+    }));
+    for (status, description) in errors {
+        responses.insert(status.to_string(), json!({
+            "description": description,
+            "content": json_content(error_reference()),
+        }));
+    }
+    Value::Object(responses)
+}
+
+/// 401 can come from API Gateway when the authorizer denies, or from the handler.
+fn with_unauthorized(responses: Value, message_response: Value) -> Value {
+    let Value::Object(responses) = responses else {
+        return responses;
+    };
+    let mut responses: Vec<(String, Value)> = responses.into_iter().collect();
+    responses.push(("401".to_string(), json!({
+        "description": "Unauthorized, either from API Gateway or the handler",
+        "content": json_content(json!({ "anyOf": [message_response, error_reference()] })),
+    })));
+    responses.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Value::Object(responses.into_iter().collect())
+}
+
+fn header(name: &str, description: &str) -> Value {
+    json!({
+        "in": "header",
+        "name": name,
+        "description": description,
+        "schema": { "type": "string" },
+        "required": true,
+    })
+}
+
+/// Optional on every mutating operation, see [`idempotency`](crate::http::idempotency).
+fn idempotency_key() -> Value {
+    json!({
+        "in": "header",
+        "name": "Idempotency-Key",
+        "description": "A unique key for the request, so a retry gets the first response instead of repeating it",
+        "schema": { "type": "string", "maxLength": 255 },
+        "required": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::api::specification;
+
+    #[test]
+    fn test_checked_in_spec_is_generated() {
+        let checked_in: Value = serde_yaml::from_str(include_str!("../../../../../openapi.yaml"))
+            .expect("Failed to parse openapi.yaml");
+        assert!(checked_in == specification(), "openapi.yaml is out of date, regenerate it with `snipsnap-admin openapi`");
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Users and devices locked out of the API, checked by the authorizer after it has validated a token.
+//!
+//! Bans can expire, in which case the table's TTL eventually deletes them. Until it does, expired bans are ignored.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::database::{Database, Error, Item};
+use crate::database::schema::{AttributeType, KeyAttribute, KeySchema, TableSchema};
+
+pub struct BannedSubjectsTable {}
+
+/// Who a ban applies to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Subject {
+    User(String),
+    Device(String),
+}
+
+impl Subject {
+    /// e.g. `user#abc`, so users and devices share the table without their ids colliding.
+    fn key(&self) -> String {
+        match self {
+            Subject::User(user_id) => format!("{USER_PREFIX}{user_id}"),
+            Subject::Device(device_id) => format!("{DEVICE_PREFIX}{device_id}"),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Subject> {
+        if let Some(user_id) = key.strip_prefix(USER_PREFIX) {
+            return Some(Subject::User(String::from(user_id)));
+        }
+        key.strip_prefix(DEVICE_PREFIX).map(|device_id| Subject::Device(String::from(device_id)))
+    }
+}
+
+impl Display for Subject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subject::User(user_id) => write!(f, "user {user_id}"),
+            Subject::Device(device_id) => write!(f, "device {device_id}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ban {
+    subject: Subject,
+    reason: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    /// A ban lasting until `expires_at`, or forever.
+    pub fn new(subject: Subject, reason: &str, expires_at: Option<DateTime<Utc>>) -> Ban {
+        Ban {
+            subject,
+            reason: String::from(reason),
+            expires_at,
+        }
+    }
+
+    fn from_item(item: &Item) -> Result<Ban, Error> {
+        let (Some(AttributeValue::S(key)), Some(AttributeValue::S(reason))) = (item.get(SUBJECT_ATTRIBUTE), item.get(REASON_ATTRIBUTE)) else {
+            return Err(Error::AttributeError);
+        };
+        let expires_at = match item.get(EXPIRES_AT_ATTRIBUTE) {
+            Some(AttributeValue::N(seconds)) => {
+                let seconds = seconds.parse().map_err(|_| Error::AttributeError)?;
+                Some(Utc.timestamp_opt(seconds, 0).single().ok_or(Error::AttributeError)?)
+            }
+            None => None,
+            _ => return Err(Error::AttributeError),
+        };
+        Ok(Ban {
+            subject: Subject::from_key(key).ok_or(Error::AttributeError)?,
+            reason: reason.clone(),
+            expires_at,
+        })
+    }
+
+    pub fn subject(&self) -> &Subject {
+        &self.subject
+    }
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+    /// `None` for permanent bans.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    pub fn is_active(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at > Utc::now(),
+            None => true,
+        }
+    }
+}
+
+impl BannedSubjectsTable {
+    pub fn schema() -> TableSchema {
+        TableSchema::new(TABLE_NAME, KeySchema::new(
+            KeyAttribute::new(SUBJECT_ATTRIBUTE, AttributeType::String),
+            None,
+        ))
+            .with_ttl_attribute(EXPIRES_AT_ATTRIBUTE)
+    }
+}
+
+impl BannedSubjectsTable {
+    /// Ban a subject, replacing any ban it already has.
+    pub async fn ban(database: &Database, ban: &Ban) -> Result<(), Error> {
+        let mut item = Self::key(&ban.subject);
+        item.insert(REASON_ATTRIBUTE.to_string(), AttributeValue::S(ban.reason.clone()));
+        if let Some(expires_at) = ban.expires_at {
+            item.insert(EXPIRES_AT_ATTRIBUTE.to_string(), AttributeValue::N(expires_at.timestamp().to_string()));
+        }
+
+        match database.client()
+            .put_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_item(Some(item))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::PutItem(e)),
+        }
+    }
+
+    pub async fn unban(database: &Database, subject: &Subject) -> Result<(), Error> {
+        match database.client()
+            .delete_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(subject)))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::DeleteItem(e)),
+        }
+    }
+
+    /// The subject's ban, if it has one that hasn't expired.
+    pub async fn get_ban(database: &Database, subject: &Subject) -> Result<Option<Ban>, Error> {
+        match database.client()
+            .get_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(subject)))
+            .send()
+            .await
+        {
+            Ok(output) => match output.item() {
+                Some(item) => Ok(Some(Ban::from_item(item)?).filter(Ban::is_active)),
+                None => Ok(None),
+            },
+            Err(e) => Err(Error::GetItem(e)),
+        }
+    }
+
+    fn key(subject: &Subject) -> Item {
+        HashMap::from([(SUBJECT_ATTRIBUTE.to_string(), AttributeValue::S(subject.key()))])
+    }
+}
+
+const TABLE_NAME: &str = "banned-subjects";
+const SUBJECT_ATTRIBUTE: &str = "subject";
+const REASON_ATTRIBUTE: &str = "reason";
+const EXPIRES_AT_ATTRIBUTE: &str = "expiresAt";
+const USER_PREFIX: &str = "user#";
+const DEVICE_PREFIX: &str = "device#";
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::database::banned_subjects_table::{Ban, Subject};
+
+    #[test]
+    fn test_subject_key() {
+        let user = Subject::User(String::from("device#1"));
+        assert_eq!(user.key(), "user#device#1");
+        assert_eq!(Subject::from_key(&user.key()), Some(user));
+        assert_eq!(Subject::from_key("device#1"), Some(Subject::Device(String::from("1"))));
+        assert_eq!(Subject::from_key("1"), None);
+    }
+
+    #[test]
+    fn test_is_active() {
+        let subject = Subject::User(String::from("user"));
+        assert!(Ban::new(subject.clone(), "spam", None).is_active());
+        assert!(Ban::new(subject.clone(), "spam", Some(Utc::now() + Duration::hours(1))).is_active());
+        assert!(!Ban::new(subject, "spam", Some(Utc::now() - Duration::hours(1))).is_active());
+    }
+}
@@ -0,0 +1,178 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Opaque pagination tokens.
+//!
+//! A token is the `LastEvaluatedKey` of a query and the query's scope serialized to json, followed by an HMAC of that
+//! json, both base64 encoded. Clients can read the key but can't forge one, so they can't use a token to page through
+//! items they couldn't query, or replay it against a different query.
+
+use std::collections::HashMap;
+use std::env;
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_dynamodb::types::Blob;
+use hmac::{Hmac, Mac};
+use rand::{Rng, thread_rng};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+use crate::database::{DatabaseConfig, Error};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SEPARATOR: char = '.';
+const SECRET_VARIABLE: &str = "SNIPSNAP_CURSOR_SECRET";
+const SCOPE_FIELD: &str = "scope";
+const KEY_FIELD: &str = "key";
+
+#[derive(Clone)]
+pub struct CursorCodec {
+    secret: Vec<u8>,
+}
+
+impl CursorCodec {
+
+    pub fn new(secret: &[u8]) -> CursorCodec {
+        CursorCodec { secret: secret.to_vec() }
+    }
+
+    /// A codec with a random secret. Its tokens are only valid within the current process.
+    pub fn ephemeral() -> CursorCodec {
+        CursorCodec { secret: thread_rng().gen::<[u8; 32]>().to_vec() }
+    }
+
+    /// Read `SNIPSNAP_CURSOR_SECRET`, which every lambda container has to share for a token from one to work on the
+    /// next. It can only be left out locally or on the dev stage, where tokens are then
+    /// [ephemeral](CursorCodec::ephemeral).
+    ///
+    /// Only lambdas that page through queries for clients need one.
+    pub fn from_env() -> Result<CursorCodec, Error> {
+        match env::var(SECRET_VARIABLE).ok().filter(|s| !s.is_empty()) {
+            Some(secret) => Ok(CursorCodec::new(secret.as_bytes())),
+            None if DatabaseConfig::from_env().is_local() => Ok(CursorCodec::ephemeral()),
+            None => Err(Error::MissingCursorSecret),
+        }
+    }
+
+    /// A token for continuing after `key` in the query identified by `scope`.
+    pub fn encode(&self, scope: &str, key: &HashMap<String, AttributeValue>) -> Result<String, Error> {
+        let mut map = Map::new();
+        for (name, value) in key {
+            map.insert(name.clone(), encode_attribute(value)?);
+        }
+        let mut payload = Map::new();
+        payload.insert(SCOPE_FIELD.to_string(), Value::String(scope.to_string()));
+        payload.insert(KEY_FIELD.to_string(), Value::Object(map));
+        let payload = Value::Object(payload).to_string();
+        let signature = self.mac(payload.as_bytes()).finalize().into_bytes();
+        Ok(format!(
+            "{}{SEPARATOR}{}",
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        ))
+    }
+
+    /// The key to continue after, if `token` was issued for the query identified by `scope`.
+    pub fn decode(&self, scope: &str, token: &str) -> Result<HashMap<String, AttributeValue>, Error> {
+        let (payload, signature) = token.split_once(SEPARATOR).ok_or(Error::InvalidCursor)?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).map_err(|_| Error::InvalidCursor)?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).map_err(|_| Error::InvalidCursor)?;
+        self.mac(&payload).verify_slice(&signature).map_err(|_| Error::InvalidCursor)?;
+
+        let Ok(Value::Object(mut payload)) = serde_json::from_slice(&payload) else {
+            return Err(Error::InvalidCursor);
+        };
+        if payload.get(SCOPE_FIELD).and_then(Value::as_str) != Some(scope) {
+            return Err(Error::InvalidCursor);
+        }
+        match payload.remove(KEY_FIELD) {
+            Some(Value::Object(map)) => map.into_iter()
+                .map(|(name, value)| decode_attribute(value).map(|v| (name, v)))
+                .collect(),
+            _ => Err(Error::InvalidCursor),
+        }
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(payload);
+        mac
+    }
+}
+
+// key attributes can only be strings, numbers or binary
+fn encode_attribute(value: &AttributeValue) -> Result<Value, Error> {
+    let (type_name, value) = match value {
+        AttributeValue::S(s) => ("S", s.clone()),
+        AttributeValue::N(n) => ("N", n.clone()),
+        AttributeValue::B(b) => ("B", base64::encode(b.as_ref())),
+        _ => return Err(Error::AttributeError),
+    };
+    let mut map = Map::new();
+    map.insert(type_name.to_string(), Value::String(value));
+    Ok(Value::Object(map))
+}
+
+fn decode_attribute(value: Value) -> Result<AttributeValue, Error> {
+    let Value::Object(map) = value else {
+        return Err(Error::InvalidCursor);
+    };
+    match map.into_iter().next() {
+        Some((type_name, Value::String(value))) => match type_name.as_str() {
+            "S" => Ok(AttributeValue::S(value)),
+            "N" => Ok(AttributeValue::N(value)),
+            "B" => base64::decode(value)
+                .map(|b| AttributeValue::B(Blob::new(b)))
+                .map_err(|_| Error::InvalidCursor),
+            _ => Err(Error::InvalidCursor),
+        },
+        _ => Err(Error::InvalidCursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+
+    use crate::database::cursor::CursorCodec;
+
+    fn key() -> HashMap<String, AttributeValue> {
+        let mut key = HashMap::new();
+        key.insert("userId".to_string(), AttributeValue::S("user".to_string()));
+        key.insert("timestamp".to_string(), AttributeValue::N("1662668109818".to_string()));
+        key
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let codec = CursorCodec::new(b"secret");
+        let token = codec.encode("logins/user", &key()).expect("Failed to encode");
+        assert_eq!(codec.decode("logins/user", &token).expect("Failed to decode"), key());
+    }
+
+    #[test]
+    fn test_tampered() {
+        let codec = CursorCodec::new(b"secret");
+        let token = codec.encode("logins/user", &key()).expect("Failed to encode");
+        let (_, signature) = token.split_once('.').expect("Missing separator");
+        let mut forged = key();
+        forged.insert("userId".to_string(), AttributeValue::S("someone else".to_string()));
+        let forged_payload = codec.encode("logins/user", &forged).expect("Failed to encode");
+        let (forged_payload, _) = forged_payload.split_once('.').expect("Missing separator");
+
+        assert!(codec.decode("logins/user", &format!("{forged_payload}.{signature}")).is_err());
+        assert!(CursorCodec::new(b"other secret").decode("logins/user", &token).is_err());
+        assert!(codec.decode("logins/user", "not a token").is_err());
+    }
+
+    #[test]
+    fn test_other_scope() {
+        let codec = CursorCodec::new(b"secret");
+        let token = codec.encode("logins/user", &key()).expect("Failed to encode");
+        assert!(codec.decode("logins/someone else", &token).is_err(), "decoded a token for another query");
+    }
+}
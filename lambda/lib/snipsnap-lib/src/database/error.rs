@@ -2,22 +2,47 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use aws_sdk_dynamodb::error::{DeleteItemError, PutItemError, QueryError, UpdateItemError};
+use aws_sdk_dynamodb::error::{DeleteItemError, GetItemError, PutItemError, QueryError, TransactWriteItemsError, UpdateItemError};
 use aws_sdk_dynamodb::types::SdkError;
 use thiserror::Error;
 
+use crate::database::Item;
+use crate::database::transaction::ItemError;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Failed to execute query")]
     Query(#[from] SdkError<QueryError>),
+    #[error("Failed to get item")]
+    GetItem(#[from] SdkError<GetItemError>),
     #[error("Failed to put item")]
     PutItem(#[from] SdkError<PutItemError>),
     #[error("Failed to update item")]
     UpdateItem(#[from] SdkError<UpdateItemError>),
     #[error("Failed to delete item")]
     DeleteItem(#[from] SdkError<DeleteItemError>),
+    #[error("Failed to write transaction")]
+    TransactWriteItems(#[from] SdkError<TransactWriteItemsError>),
+    #[error("Transaction cancelled: {}", .0.iter().map(ToString::to_string).collect::<Vec<String>>().join(", "))]
+    TransactionCanceled(Vec<ItemError>),
+    #[error("Transaction has {0} items, more than DynamoDB allows")]
+    TransactionTooLarge(usize),
+    /// The item was changed since the version the write was based on. Holds the current item, if it still exists.
+    #[error("Item was modified by another request")]
+    VersionConflict(Option<Item>),
+    /// An idempotency key expired and was claimed by another request, or released, before the response was stored.
+    #[error("Lost the lease on the idempotency key")]
+    LeaseLost,
     #[error("Item not found")]
     NotFound,
     #[error("Some item(s) were found but there was an error retrieving attributes")]
-    AttributeError
+    AttributeError,
+    #[error("Invalid pagination token")]
+    InvalidCursor,
+    #[error("Invalid DynamoDB endpoint {0}")]
+    InvalidEndpoint(String),
+    #[error("SNIPSNAP_CURSOR_SECRET must be set outside of local use and the dev stage")]
+    MissingCursorSecret,
+    #[error("Page size {0} is not between 1 and {max}", max = crate::database::pagination::MAX_PAGE_SIZE)]
+    InvalidPageSize(i32),
 }
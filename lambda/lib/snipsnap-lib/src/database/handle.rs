@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::env;
+
+use aws_sdk_dynamodb::{Client, Config, Credentials, Endpoint, Region};
+
+use crate::database::Error;
+use crate::{DEV_STAGE, REGION, STAGE_VARIABLE};
+
+const ENDPOINT_VARIABLE: &str = "SNIPSNAP_DYNAMODB_ENDPOINT";
+const REGION_VARIABLE: &str = "AWS_REGION";
+
+/// Where the tables live and which stage's copies of them to use.
+#[derive(Clone, Debug, Default)]
+pub struct DatabaseConfig {
+    stage: Option<String>,
+    endpoint: Option<String>,
+    region: Option<String>,
+}
+
+impl DatabaseConfig {
+
+    /// Read `SNIPSNAP_STAGE`, `SNIPSNAP_DYNAMODB_ENDPOINT` and `AWS_REGION`, all optional.
+    pub fn from_env() -> DatabaseConfig {
+        DatabaseConfig {
+            stage: env::var(STAGE_VARIABLE).ok().filter(|s| !s.is_empty()),
+            endpoint: env::var(ENDPOINT_VARIABLE).ok().filter(|s| !s.is_empty()),
+            region: env::var(REGION_VARIABLE).ok().filter(|s| !s.is_empty()),
+        }
+    }
+
+    pub fn with_stage(mut self, stage: impl Into<String>) -> DatabaseConfig {
+        self.stage = Some(stage.into());
+        self
+    }
+    /// Talk to a different endpoint than the real service, e.g. `http://localhost:8000` for DynamoDB Local.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> DatabaseConfig {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+    pub fn with_region(mut self, region: impl Into<String>) -> DatabaseConfig {
+        self.region = Some(region.into());
+        self
+    }
+
+    pub fn stage(&self) -> Option<&str> {
+        self.stage.as_deref()
+    }
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+    pub fn region(&self) -> &str {
+        self.region.as_deref().unwrap_or(REGION)
+    }
+
+    /// Running against DynamoDB Local, without a stage, or on the dev stage.
+    pub(crate) fn is_local(&self) -> bool {
+        self.endpoint.is_some() || matches!(self.stage(), None | Some(DEV_STAGE))
+    }
+}
+
+/// Shared handle to DynamoDB, created once per process and passed to the table functions. Clones share the client.
+#[derive(Clone)]
+pub struct Database {
+    client: Client,
+    config: DatabaseConfig,
+}
+
+impl Database {
+
+    /// Fails if the configured endpoint is not a valid uri.
+    pub fn new(config: DatabaseConfig) -> Result<Database, Error> {
+        let mut builder = Config::builder().region(Region::new(config.region().to_string()));
+        match config.endpoint() {
+            Some(endpoint) => {
+                let uri = endpoint.parse().map_err(|_| Error::InvalidEndpoint(endpoint.to_string()))?;
+                // DynamoDB Local accepts any credentials
+                builder = builder
+                    .endpoint_resolver(Endpoint::immutable(uri))
+                    .credentials_provider(Credentials::new("local", "local", None, None, "local"));
+            }
+            None => {
+                // set by the lambda runtime
+                if let (Ok(access_key_id), Ok(secret_access_key)) = (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+                    let session_token = env::var("AWS_SESSION_TOKEN").ok();
+                    builder = builder.credentials_provider(Credentials::new(access_key_id, secret_access_key, session_token, None, "environment"));
+                }
+            }
+        }
+
+        Ok(Database {
+            client: Client::from_conf(builder.build()),
+            config,
+        })
+    }
+
+    pub fn from_env() -> Result<Database, Error> {
+        Database::new(DatabaseConfig::from_env())
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+    pub fn config(&self) -> &DatabaseConfig {
+        &self.config
+    }
+
+    /// The name of a table for the configured stage, e.g. `dev-nonces`. Without a stage the base name is used.
+    pub fn table_name(&self, base_name: &str) -> String {
+        match self.config.stage() {
+            Some(stage) => format!("{stage}-{base_name}"),
+            None => base_name.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{Database, DatabaseConfig};
+
+    #[test]
+    fn test_table_name() {
+        let database = Database::new(DatabaseConfig::default()).expect("Failed to create database");
+        assert_eq!(database.table_name("nonces"), "nonces");
+        let database = Database::new(DatabaseConfig::default().with_stage("dev")).expect("Failed to create database");
+        assert_eq!(database.table_name("nonces"), "dev-nonces");
+    }
+
+    #[test]
+    fn test_invalid_endpoint() {
+        assert!(Database::new(DatabaseConfig::default().with_endpoint("not a uri")).is_err());
+    }
+}
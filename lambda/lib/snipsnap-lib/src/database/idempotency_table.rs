@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Responses to requests sent with an `Idempotency-Key`, so a retried request gets the first response instead of
+//! doing the work again. See [`Idempotency`](crate::http::idempotency::Idempotency).
+//!
+//! A record is written when a request claims its key, before the handler runs, and completed with the response once
+//! it has one. A claim only lasts a short lease, so a request that never completes frees its key soon, and completing
+//! it extends the record to the full TTL. Completing and releasing only touch the record while the request still
+//! holds it, so a request that outlived its lease can't overwrite or delete another request's claim.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_dynamodb::types::{Blob, SdkError};
+use chrono::{DateTime, Utc};
+
+use crate::database::{Database, Error, Item};
+use crate::database::schema::{AttributeType, KeyAttribute, KeySchema, TableSchema};
+
+pub struct IdempotencyTable {}
+
+/// A claimed key and, once the request has finished, its response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdempotencyRecord {
+    request_hash: String,
+    response: Option<StoredResponse>,
+}
+
+impl IdempotencyRecord {
+    fn from_item(item: &Item) -> Result<IdempotencyRecord, Error> {
+        let Some(AttributeValue::S(request_hash)) = item.get(REQUEST_HASH_ATTRIBUTE) else {
+            return Err(Error::AttributeError);
+        };
+        let response = match (item.get(STATUS_ATTRIBUTE), item.get(HEADERS_ATTRIBUTE), item.get(BODY_ATTRIBUTE)) {
+            (Some(AttributeValue::N(status)), Some(AttributeValue::S(headers)), Some(AttributeValue::B(body))) => Some(StoredResponse {
+                status: status.parse().map_err(|_| Error::AttributeError)?,
+                headers: serde_json::from_str(headers).map_err(|_| Error::AttributeError)?,
+                body: body.as_ref().to_vec(),
+            }),
+            (None, None, None) => None,
+            _ => return Err(Error::AttributeError),
+        };
+        Ok(IdempotencyRecord { request_hash: request_hash.clone(), response })
+    }
+
+    /// Identifies the request that claimed the key, so a different request reusing it can be told apart.
+    pub fn request_hash(&self) -> &str {
+        &self.request_hash
+    }
+    /// `None` while the first request is still running.
+    pub fn response(&self) -> Option<&StoredResponse> {
+        self.response.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl StoredResponse {
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> StoredResponse {
+        StoredResponse { status, headers, body }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+impl IdempotencyTable {
+    pub fn schema() -> TableSchema {
+        TableSchema::new(TABLE_NAME, KeySchema::new(
+            KeyAttribute::new(KEY_ATTRIBUTE, AttributeType::String),
+            None,
+        ))
+            .with_ttl_attribute(EXPIRES_AT_ATTRIBUTE)
+    }
+}
+
+impl IdempotencyTable {
+    /// Claim `key` for the request identified by `request_hash` until `expires_at`, which should be a short lease.
+    ///
+    /// Returns `None` if the key was free, and the existing record if another request already claimed it. Records
+    /// past their expiry count as free, since TTL deletes them only eventually.
+    pub async fn claim(database: &Database, key: &str, request_hash: &str, expires_at: DateTime<Utc>) -> Result<Option<IdempotencyRecord>, Error> {
+        let table_name = database.table_name(TABLE_NAME);
+        let item = HashMap::from([
+            (KEY_ATTRIBUTE.to_string(), AttributeValue::S(key.to_string())),
+            (REQUEST_HASH_ATTRIBUTE.to_string(), AttributeValue::S(request_hash.to_string())),
+            (EXPIRES_AT_ATTRIBUTE.to_string(), AttributeValue::N(expires_at.timestamp().to_string())),
+        ]);
+
+        match database.client()
+            .put_item()
+            .table_name(&table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(#key) OR #expiresAt < :now")
+            .expression_attribute_names("#key", KEY_ATTRIBUTE)
+            .expression_attribute_names("#expiresAt", EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_values(":now", AttributeValue::N(Utc::now().timestamp().to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(None),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => {
+                match database.client()
+                    .get_item()
+                    .table_name(&table_name)
+                    .set_key(Some(Self::key(key)))
+                    .consistent_read(true)
+                    .send()
+                    .await
+                {
+                    Ok(output) => match output.item() {
+                        Some(item) => IdempotencyRecord::from_item(item).map(Some),
+                        // deleted between the put and the get
+                        None => Err(Error::NotFound),
+                    },
+                    Err(e) => Err(Error::GetItem(e)),
+                }
+            }
+            Err(e) => Err(Error::PutItem(e)),
+        }
+    }
+
+    /// Store the response to the request that claimed `key` with `request_hash`, and keep it until `expires_at`.
+    ///
+    /// Fails with [`Error::LeaseLost`] if the key was released or claimed by another request in the meantime.
+    pub async fn complete(database: &Database, key: &str, request_hash: &str, response: &StoredResponse, expires_at: DateTime<Utc>) -> Result<(), Error> {
+        let headers = serde_json::to_string(&response.headers).map_err(|_| Error::AttributeError)?;
+        match database.client()
+            .update_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(key)))
+            .update_expression("SET #status = :status, #headers = :headers, #body = :body, #expiresAt = :expiresAt")
+            .condition_expression(HELD_CONDITION)
+            .expression_attribute_names("#key", KEY_ATTRIBUTE)
+            .expression_attribute_names("#requestHash", REQUEST_HASH_ATTRIBUTE)
+            .expression_attribute_names("#status", STATUS_ATTRIBUTE)
+            .expression_attribute_names("#headers", HEADERS_ATTRIBUTE)
+            .expression_attribute_names("#body", BODY_ATTRIBUTE)
+            .expression_attribute_names("#expiresAt", EXPIRES_AT_ATTRIBUTE)
+            .expression_attribute_values(":hash", AttributeValue::S(request_hash.to_string()))
+            .expression_attribute_values(":status", AttributeValue::N(response.status.to_string()))
+            .expression_attribute_values(":headers", AttributeValue::S(headers))
+            .expression_attribute_values(":body", AttributeValue::B(Blob::new(response.body.clone())))
+            .expression_attribute_values(":expiresAt", AttributeValue::N(expires_at.timestamp().to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Err(Error::LeaseLost),
+            Err(e) => Err(Error::UpdateItem(e))
+        }
+    }
+
+    /// Give up `key` without a response, so the request can be retried. Like [`complete`](Self::complete), only while
+    /// the request that claimed it with `request_hash` still holds it.
+    pub async fn release(database: &Database, key: &str, request_hash: &str) -> Result<(), Error> {
+        match database.client()
+            .delete_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(key)))
+            .condition_expression(HELD_CONDITION)
+            .expression_attribute_names("#key", KEY_ATTRIBUTE)
+            .expression_attribute_names("#requestHash", REQUEST_HASH_ATTRIBUTE)
+            .expression_attribute_values(":hash", AttributeValue::S(request_hash.to_string()))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => Err(Error::LeaseLost),
+            Err(e) => Err(Error::DeleteItem(e))
+        }
+    }
+
+    fn key(key: &str) -> Item {
+        HashMap::from([(KEY_ATTRIBUTE.to_string(), AttributeValue::S(key.to_string()))])
+    }
+}
+
+const TABLE_NAME: &str = "idempotency";
+const KEY_ATTRIBUTE: &str = "key";
+const REQUEST_HASH_ATTRIBUTE: &str = "requestHash";
+const STATUS_ATTRIBUTE: &str = "status";
+const HEADERS_ATTRIBUTE: &str = "headers";
+const BODY_ATTRIBUTE: &str = "body";
+const EXPIRES_AT_ATTRIBUTE: &str = "expiresAt";
+/// The record still exists and was claimed by the same request.
+const HELD_CONDITION: &str = "attribute_exists(#key) AND #requestHash = :hash";
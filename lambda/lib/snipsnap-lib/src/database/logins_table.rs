@@ -2,31 +2,60 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use aws_sdk_dynamodb::{Client, Config, Region};
-use aws_sdk_dynamodb::model::AttributeValue;
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::model::{AttributeValue, Put};
 use chrono::prelude::*;
+use serde::Serialize;
 
-use crate::database::Error;
-use crate::REGION;
+use crate::database::{Database, Error, Item, Page};
+use crate::database::cursor::CursorCodec;
+use crate::database::pagination::query_page;
+use crate::database::schema::{AttributeType, KeyAttribute, KeySchema, TableSchema};
 
 pub struct LoginsTable {}
 
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+pub struct Login {
+    userId: String,
+    timestamp: String,
+}
+
+impl Login {
+    fn from_item(item: Item) -> Result<Login, Error> {
+        match (item.get(USER_ID_ATTRIBUTE), item.get(TIMESTAMP_ATTRIBUTE)) {
+            (Some(AttributeValue::S(user_id)), Some(AttributeValue::S(timestamp))) => Ok(Login {
+                userId: user_id.clone(),
+                timestamp: timestamp.clone(),
+            }),
+            _ => Err(Error::AttributeError),
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.userId
+    }
+    pub fn timestamp(&self) -> &str {
+        &self.timestamp
+    }
+}
+
 impl LoginsTable {
-    fn client() -> Client {
-        let shared_config = Config::builder()
-            .region(Region::new(REGION))
-            .build();
-        Client::from_conf(shared_config)
+    pub fn schema() -> TableSchema {
+        TableSchema::new(TABLE_NAME, KeySchema::new(
+            KeyAttribute::new(USER_ID_ATTRIBUTE, AttributeType::String),
+            Some(KeyAttribute::new(TIMESTAMP_ATTRIBUTE, AttributeType::String)),
+        ))
     }
 }
 
 impl LoginsTable {
-    pub async fn record_login(user_id: &str) -> Result<(), Error> {
-        match Self::client()
+    pub async fn record_login(database: &Database, user_id: &str) -> Result<(), Error> {
+        match database.client()
             .put_item()
-            .table_name(TABLE_NAME)
-            .item(USER_ID_ATTRIBUTE, AttributeValue::S(user_id.to_string()))
-            .item(TIMESTAMP_ATTRIBUTE, AttributeValue::S(Utc::now().to_string()))
+            .table_name(database.table_name(TABLE_NAME))
+            .set_item(Some(Self::login_item(user_id)))
             .send()
             .await
         {
@@ -34,6 +63,34 @@ impl LoginsTable {
             Err(e) => Err(Error::PutItem(e))
         }
     }
+
+    /// [`record_login`](LoginsTable::record_login) as part of a [`Transaction`](crate::database::Transaction).
+    pub fn put_login(database: &Database, user_id: &str) -> Put {
+        Put::builder()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_item(Some(Self::login_item(user_id)))
+            .build()
+    }
+
+    fn login_item(user_id: &str) -> Item {
+        HashMap::from([
+            (USER_ID_ATTRIBUTE.to_string(), AttributeValue::S(user_id.to_string())),
+            (TIMESTAMP_ATTRIBUTE.to_string(), AttributeValue::S(Utc::now().to_string())),
+        ])
+    }
+
+    /// A user's logins, most recent first.
+    pub async fn list_logins(database: &Database, cursors: &CursorCodec, user_id: &str, page_size: i32, next_token: Option<&str>) -> Result<Page<Login>, Error> {
+        let table_name = database.table_name(TABLE_NAME);
+        let scope = format!("{table_name}/{user_id}");
+        let query = database.client().query()
+            .table_name(table_name)
+            .key_condition_expression("#key = :value")
+            .expression_attribute_names("#key", USER_ID_ATTRIBUTE)
+            .expression_attribute_values(":value", AttributeValue::S(user_id.to_string()))
+            .scan_index_forward(false);
+        query_page(cursors, &scope, query, page_size, next_token).await?.try_map(Login::from_item)
+    }
 }
 
 const TABLE_NAME: &str = "logins";
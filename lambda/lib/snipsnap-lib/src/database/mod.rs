@@ -4,8 +4,22 @@
 
 pub mod nonces_table;
 pub mod logins_table;
+pub mod idempotency_table;
+pub mod banned_subjects_table;
 pub mod error;
+pub mod cursor;
+pub mod pagination;
+pub mod transaction;
+pub mod versioned;
+pub mod handle;
+pub mod schema;
 
 pub use nonces_table::NoncesTable;
-pub use logins_table::LoginsTable;
+pub use logins_table::{Login, LoginsTable};
+pub use idempotency_table::IdempotencyTable;
+pub use banned_subjects_table::{Ban, BannedSubjectsTable, Subject};
 pub use error::Error;
+pub use handle::{Database, DatabaseConfig};
+pub use pagination::{Item, Page};
+pub use transaction::Transaction;
+pub use schema::TableSchema;
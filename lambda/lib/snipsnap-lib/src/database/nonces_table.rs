@@ -2,38 +2,40 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use aws_sdk_dynamodb::{Client, Config, Region};
-use aws_sdk_dynamodb::model::{AttributeValue, Select};
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::model::{AttributeValue, ConditionCheck, Delete, Select};
 use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
 
-use crate::database::Error;
-use crate::REGION;
+use crate::database::{Database, Error, Item};
+use crate::database::schema::{AttributeType, KeyAttribute, KeySchema, TableSchema};
 
 pub struct NoncesTable {}
 
 impl NoncesTable {
-    fn client() -> Client {
-        let shared_config = Config::builder()
-            .region(Region::new(REGION))
-            .build();
-        Client::from_conf(shared_config)
+    pub fn schema() -> TableSchema {
+        TableSchema::new(TABLE_NAME, KeySchema::new(
+            KeyAttribute::new(DEVICE_ID_ATTRIBUTE, AttributeType::String),
+            None,
+        ))
     }
 }
 
 // nonce functionality
 impl NoncesTable {
-    pub async fn make_nonce(device_id: &str) -> Result<String, Error> {
+    pub async fn make_nonce(database: &Database, device_id: &str) -> Result<String, Error> {
         let nonce: String = thread_rng()
             .sample_iter(&Alphanumeric)
             .take(NONCE_LENGTH)
             .map(char::from)
             .collect();
 
-        let client = Self::client();
+        let client = database.client();
+        let table_name = database.table_name(TABLE_NAME);
 
         match client.query()
-            .table_name(TABLE_NAME)
+            .table_name(&table_name)
             .key_condition_expression("#key = :value")
             .expression_attribute_names("#key", DEVICE_ID_ATTRIBUTE)
             .expression_attribute_values(":value", AttributeValue::S(String::from(device_id)))
@@ -44,7 +46,7 @@ impl NoncesTable {
             Ok(resp) => {
                 if resp.count > 0 {
                     match client.update_item()
-                        .table_name(TABLE_NAME)
+                        .table_name(&table_name)
                         .key(DEVICE_ID_ATTRIBUTE, AttributeValue::S(String::from(device_id)))
                         .update_expression("SET #key = :value")
                         .expression_attribute_names("#key", NONCE_ATTRIBUTE)
@@ -57,7 +59,7 @@ impl NoncesTable {
                     }
                 } else {
                     match client.put_item()
-                        .table_name(TABLE_NAME)
+                        .table_name(&table_name)
                         .item(DEVICE_ID_ATTRIBUTE, AttributeValue::S(String::from(device_id)))
                         .item(NONCE_ATTRIBUTE, AttributeValue::S(String::from(nonce.clone())))
                         .send()
@@ -72,42 +74,28 @@ impl NoncesTable {
         }
     }
 
-    async fn read_nonce(device_id: &str) -> Result<String, Error> {
-        match Self::client().query()
-            .table_name(TABLE_NAME)
-            .key_condition_expression("#key = :value")
-            .expression_attribute_names("#key", DEVICE_ID_ATTRIBUTE)
-            .expression_attribute_values(":value", AttributeValue::S(String::from(device_id)))
-            .select(Select::AllAttributes)
+    async fn read_nonce(database: &Database, device_id: &str) -> Result<String, Error> {
+        match database.client().get_item()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(device_id)))
             .send()
             .await
         {
-            Ok(resp) => {
-                if resp.count() > 0 {
-                    if let Some(items) = resp.items() {
-                        if let Some(first) = items.first() {
-                            if let Some(attribute_value) = first.get(NONCE_ATTRIBUTE) {
-                                if let AttributeValue::S(nonce) = attribute_value {
-                                    return Ok(String::from(nonce))
-                                }
-                            }
-                        }
-                    }
-                    Err(Error::AttributeError)
-                } else {
-                    Err(Error::NotFound)
-                }
+            Ok(output) => match output.item().map(|item| item.get(NONCE_ATTRIBUTE)) {
+                Some(Some(AttributeValue::S(nonce))) => Ok(String::from(nonce)),
+                Some(_) => Err(Error::AttributeError),
+                None => Err(Error::NotFound),
             },
-            Err(e) => Err(Error::Query(e))
+            Err(e) => Err(Error::GetItem(e))
         }
     }
 
-    pub async fn get_nonce(device_id: &str) -> Result<String, Error> {
-        match Self::read_nonce(device_id).await {
+    pub async fn get_nonce(database: &Database, device_id: &str) -> Result<String, Error> {
+        match Self::read_nonce(database, device_id).await {
             Ok(nonce) => {
-                match Self::client().delete_item()
-                    .table_name(TABLE_NAME)
-                    .key(DEVICE_ID_ATTRIBUTE, AttributeValue::S(String::from(device_id)))
+                match database.client().delete_item()
+                    .table_name(database.table_name(TABLE_NAME))
+                    .set_key(Some(Self::key(device_id)))
                     .send()
                     .await
                 {
@@ -120,6 +108,32 @@ impl NoncesTable {
     }
 }
 
+// transaction operations
+impl NoncesTable {
+    /// Remove a device's nonce as part of a [`Transaction`](crate::database::Transaction).
+    pub fn delete_nonce(database: &Database, device_id: &str) -> Delete {
+        Delete::builder()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(device_id)))
+            .build()
+    }
+
+    /// Cancel a [`Transaction`](crate::database::Transaction) unless the device currently holds `nonce`.
+    pub fn check_nonce(database: &Database, device_id: &str, nonce: &str) -> ConditionCheck {
+        ConditionCheck::builder()
+            .table_name(database.table_name(TABLE_NAME))
+            .set_key(Some(Self::key(device_id)))
+            .condition_expression("#key = :value")
+            .expression_attribute_names("#key", NONCE_ATTRIBUTE)
+            .expression_attribute_values(":value", AttributeValue::S(String::from(nonce)))
+            .build()
+    }
+
+    fn key(device_id: &str) -> Item {
+        HashMap::from([(DEVICE_ID_ATTRIBUTE.to_string(), AttributeValue::S(String::from(device_id)))])
+    }
+}
+
 const TABLE_NAME: &str = "nonces";
 const DEVICE_ID_ATTRIBUTE: &str = "deviceId";
 const NONCE_ATTRIBUTE: &str = "nonce";
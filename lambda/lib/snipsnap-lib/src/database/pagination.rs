@@ -0,0 +1,143 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::client::fluent_builders::Query;
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::database::Error;
+use crate::database::cursor::CursorCodec;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+/// The most items [`query_page`] returns at once.
+pub const MAX_PAGE_SIZE: i32 = 100;
+
+/// One page of results. `nextToken` is absent on the last page.
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+pub struct Page<T> {
+    items: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nextToken: Option<String>,
+}
+
+impl<T> Page<T> {
+
+    pub fn new(items: Vec<T>, next_token: Option<String>) -> Page<T> {
+        Page {
+            items,
+            nextToken: next_token,
+        }
+    }
+
+    pub fn items(&self) -> &Vec<T> {
+        &self.items
+    }
+    pub fn next_token(&self) -> Option<&str> {
+        self.nextToken.as_deref()
+    }
+
+    /// Convert every item, e.g. from a raw [`Item`] to a table's record type.
+    pub fn try_map<U, F>(self, f: F) -> Result<Page<U>, Error> where F: FnMut(T) -> Result<U, Error> {
+        Ok(Page {
+            items: self.items.into_iter().map(f).collect::<Result<Vec<U>, Error>>()?,
+            nextToken: self.nextToken,
+        })
+    }
+}
+
+/// An item as plain JSON, e.g. `{"userId": "abc", "version": 3}` rather than DynamoDB's typed `{"N": "3"}`. Binary
+/// values become base64 strings and sets become arrays.
+pub fn item_to_json(item: &Item) -> Value {
+    Value::Object(item.iter().map(|(name, value)| (name.clone(), attribute_to_json(value))).collect())
+}
+
+fn attribute_to_json(value: &AttributeValue) -> Value {
+    let number = |n: &String| n.parse::<Number>().map_or_else(|_| Value::String(n.clone()), Value::Number);
+    match value {
+        AttributeValue::S(s) => Value::String(s.clone()),
+        AttributeValue::N(n) => number(n),
+        AttributeValue::Bool(b) => Value::Bool(*b),
+        AttributeValue::B(b) => Value::String(base64::encode(b.as_ref())),
+        AttributeValue::Ss(ss) => Value::Array(ss.iter().cloned().map(Value::String).collect()),
+        AttributeValue::Ns(ns) => Value::Array(ns.iter().map(number).collect()),
+        AttributeValue::Bs(bs) => Value::Array(bs.iter().map(|b| Value::String(base64::encode(b.as_ref()))).collect()),
+        AttributeValue::L(l) => Value::Array(l.iter().map(attribute_to_json).collect()),
+        AttributeValue::M(m) => Value::Object(m.iter().map(|(k, v)| (k.clone(), attribute_to_json(v))).collect::<Map<_, _>>()),
+        _ => Value::Null,
+    }
+}
+
+/// Run a single page of `query`, starting after `next_token` if one is given.
+///
+/// The query should already have its table, key condition and any filters set. `scope` identifies them, e.g. the
+/// table, index and key condition values, so a token issued for one query is rejected by another instead of reaching
+/// DynamoDB. `page_size` has to be between 1 and [`MAX_PAGE_SIZE`].
+pub async fn query_page(cursors: &CursorCodec, scope: &str, query: Query, page_size: i32, next_token: Option<&str>) -> Result<Page<Item>, Error> {
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(Error::InvalidPageSize(page_size));
+    }
+    let start_key = match next_token {
+        Some(token) => Some(cursors.decode(scope, token)?),
+        None => None,
+    };
+
+    let output = query
+        .limit(page_size)
+        .set_exclusive_start_key(start_key)
+        .send()
+        .await
+        .map_err(Error::Query)?;
+
+    let next_token = match output.last_evaluated_key() {
+        Some(key) if !key.is_empty() => Some(cursors.encode(scope, key)?),
+        _ => None,
+    };
+    Ok(Page::new(output.items().unwrap_or_default().to_vec(), next_token))
+}
+
+/// Every item matched by `query`, fetching further pages as the stream is read.
+pub fn query_items(query: Query) -> impl Stream<Item = Result<Item, Error>> {
+    query.into_paginator()
+        .items()
+        .send()
+        .map(|result| result.map_err(Error::Query))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+    use serde_json::json;
+
+    use crate::database::{Database, DatabaseConfig, Error};
+    use crate::database::cursor::CursorCodec;
+    use crate::database::pagination::{MAX_PAGE_SIZE, item_to_json, query_page};
+
+    #[test]
+    fn test_item_to_json() {
+        let item = HashMap::from([
+            ("userId".to_string(), AttributeValue::S("abc".to_string())),
+            ("version".to_string(), AttributeValue::N("3".to_string())),
+            ("tags".to_string(), AttributeValue::L(vec![AttributeValue::Bool(true), AttributeValue::Null(true)])),
+        ]);
+        assert_eq!(item_to_json(&item), json!({"userId": "abc", "version": 3, "tags": [true, null]}));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_page_size() {
+        let database = Database::new(DatabaseConfig::default()).expect("Failed to create database");
+        for page_size in [0, -1, MAX_PAGE_SIZE + 1] {
+            let query = database.client().query().table_name("logins");
+            let result = query_page(&CursorCodec::new(b"secret"), "logins", query, page_size, None).await;
+            assert!(matches!(result, Err(Error::InvalidPageSize(size)) if size == page_size), "accepted page size {page_size}");
+        }
+    }
+}
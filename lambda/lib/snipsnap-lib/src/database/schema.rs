@@ -0,0 +1,415 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Declarative descriptions of every DynamoDB table used by SnipSnap.
+//!
+//! Table modules describe their own layout with a [`TableSchema`], and [`tables`] collects them
+//! so tooling can create the tables or compare them against what is deployed.
+
+use std::fmt;
+
+use aws_sdk_dynamodb::model::{
+    AttributeDefinition, BillingMode as SdkBillingMode, GlobalSecondaryIndex as SdkGlobalSecondaryIndex,
+    KeySchemaElement, KeyType, Projection as SdkProjection, ProjectionType, ProvisionedThroughput,
+    ScalarAttributeType, TableDescription,
+};
+
+use crate::database::{BannedSubjectsTable, IdempotencyTable, LoginsTable, NoncesTable};
+
+/// Every table the backend expects to exist.
+pub fn tables() -> Vec<TableSchema> {
+    vec![
+        NoncesTable::schema(),
+        LoginsTable::schema(),
+        IdempotencyTable::schema(),
+        BannedSubjectsTable::schema(),
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    String,
+    Number,
+    Binary,
+}
+
+impl AttributeType {
+    fn sdk(&self) -> ScalarAttributeType {
+        match self {
+            AttributeType::String => ScalarAttributeType::S,
+            AttributeType::Number => ScalarAttributeType::N,
+            AttributeType::Binary => ScalarAttributeType::B,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyAttribute {
+    name: &'static str,
+    attribute_type: AttributeType,
+}
+
+impl KeyAttribute {
+    pub fn new(name: &'static str, attribute_type: AttributeType) -> KeyAttribute {
+        KeyAttribute { name, attribute_type }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn attribute_type(&self) -> AttributeType {
+        self.attribute_type
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySchema {
+    partition_key: KeyAttribute,
+    sort_key: Option<KeyAttribute>,
+}
+
+impl KeySchema {
+    pub fn new(partition_key: KeyAttribute, sort_key: Option<KeyAttribute>) -> KeySchema {
+        KeySchema { partition_key, sort_key }
+    }
+
+    pub fn partition_key(&self) -> &KeyAttribute {
+        &self.partition_key
+    }
+    pub fn sort_key(&self) -> Option<&KeyAttribute> {
+        self.sort_key.as_ref()
+    }
+
+    fn attributes(&self) -> Vec<&KeyAttribute> {
+        let mut attributes = vec![&self.partition_key];
+        attributes.extend(self.sort_key.iter());
+        attributes
+    }
+
+    fn sdk(&self) -> Vec<KeySchemaElement> {
+        let mut elements = vec![
+            KeySchemaElement::builder()
+                .attribute_name(self.partition_key.name)
+                .key_type(KeyType::Hash)
+                .build()
+        ];
+        if let Some(sort_key) = &self.sort_key {
+            elements.push(KeySchemaElement::builder()
+                .attribute_name(sort_key.name)
+                .key_type(KeyType::Range)
+                .build());
+        }
+        elements
+    }
+
+    fn matches(&self, elements: &[KeySchemaElement]) -> bool {
+        let expected = self.sdk();
+        expected.len() == elements.len() && expected.iter().all(|element| elements.contains(element))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    All,
+    KeysOnly,
+    Include(Vec<&'static str>),
+}
+
+impl Projection {
+    fn sdk(&self) -> SdkProjection {
+        match self {
+            Projection::All => SdkProjection::builder().projection_type(ProjectionType::All).build(),
+            Projection::KeysOnly => SdkProjection::builder().projection_type(ProjectionType::KeysOnly).build(),
+            Projection::Include(attributes) => SdkProjection::builder()
+                .projection_type(ProjectionType::Include)
+                .set_non_key_attributes(Some(attributes.iter().map(|a| a.to_string()).collect()))
+                .build(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlobalSecondaryIndex {
+    name: &'static str,
+    key: KeySchema,
+    projection: Projection,
+}
+
+impl GlobalSecondaryIndex {
+    pub fn new(name: &'static str, key: KeySchema, projection: Projection) -> GlobalSecondaryIndex {
+        GlobalSecondaryIndex { name, key, projection }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn key(&self) -> &KeySchema {
+        &self.key
+    }
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BillingMode {
+    PayPerRequest,
+    Provisioned { read_capacity: i64, write_capacity: i64 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableSchema {
+    name: &'static str,
+    key: KeySchema,
+    global_secondary_indexes: Vec<GlobalSecondaryIndex>,
+    ttl_attribute: Option<&'static str>,
+    billing_mode: BillingMode,
+}
+
+impl TableSchema {
+    /// A pay-per-request table with no indexes and no TTL.
+    pub fn new(name: &'static str, key: KeySchema) -> TableSchema {
+        TableSchema {
+            name,
+            key,
+            global_secondary_indexes: Vec::new(),
+            ttl_attribute: None,
+            billing_mode: BillingMode::PayPerRequest,
+        }
+    }
+
+    pub fn with_global_secondary_index(mut self, index: GlobalSecondaryIndex) -> TableSchema {
+        self.global_secondary_indexes.push(index);
+        self
+    }
+    pub fn with_ttl_attribute(mut self, ttl_attribute: &'static str) -> TableSchema {
+        self.ttl_attribute = Some(ttl_attribute);
+        self
+    }
+    pub fn with_billing_mode(mut self, billing_mode: BillingMode) -> TableSchema {
+        self.billing_mode = billing_mode;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn key(&self) -> &KeySchema {
+        &self.key
+    }
+    pub fn global_secondary_indexes(&self) -> &[GlobalSecondaryIndex] {
+        &self.global_secondary_indexes
+    }
+    pub fn ttl_attribute(&self) -> Option<&'static str> {
+        self.ttl_attribute
+    }
+    pub fn billing_mode(&self) -> BillingMode {
+        self.billing_mode
+    }
+}
+
+// conversions to the sdk types needed by CreateTable
+impl TableSchema {
+    /// Every attribute used by the table key or an index key, without duplicates.
+    pub fn attribute_definitions(&self) -> Vec<AttributeDefinition> {
+        let mut attributes: Vec<&KeyAttribute> = self.key.attributes();
+        for index in &self.global_secondary_indexes {
+            for attribute in index.key.attributes() {
+                if !attributes.iter().any(|a| a.name == attribute.name) {
+                    attributes.push(attribute);
+                }
+            }
+        }
+        attributes.iter()
+            .map(|a| AttributeDefinition::builder()
+                .attribute_name(a.name)
+                .attribute_type(a.attribute_type.sdk())
+                .build())
+            .collect()
+    }
+
+    pub fn key_schema(&self) -> Vec<KeySchemaElement> {
+        self.key.sdk()
+    }
+
+    pub fn sdk_global_secondary_indexes(&self) -> Option<Vec<SdkGlobalSecondaryIndex>> {
+        if self.global_secondary_indexes.is_empty() {
+            return None;
+        }
+        Some(self.global_secondary_indexes.iter()
+            .map(|index| SdkGlobalSecondaryIndex::builder()
+                .index_name(index.name)
+                .set_key_schema(Some(index.key.sdk()))
+                .projection(index.projection.sdk())
+                .set_provisioned_throughput(self.provisioned_throughput())
+                .build())
+            .collect())
+    }
+
+    pub fn sdk_billing_mode(&self) -> SdkBillingMode {
+        match self.billing_mode {
+            BillingMode::PayPerRequest => SdkBillingMode::PayPerRequest,
+            BillingMode::Provisioned { .. } => SdkBillingMode::Provisioned,
+        }
+    }
+
+    pub fn provisioned_throughput(&self) -> Option<ProvisionedThroughput> {
+        match self.billing_mode {
+            BillingMode::PayPerRequest => None,
+            BillingMode::Provisioned { read_capacity, write_capacity } => Some(ProvisionedThroughput::builder()
+                .read_capacity_units(read_capacity)
+                .write_capacity_units(write_capacity)
+                .build()),
+        }
+    }
+}
+
+// drift detection
+impl TableSchema {
+    /// Compare a deployed table against this schema.
+    ///
+    /// `ttl_attribute` is the attribute TTL is currently enabled on, since DescribeTable does not report it.
+    pub fn drift(&self, description: &TableDescription, ttl_attribute: Option<&str>) -> Vec<Drift> {
+        let mut drift = Vec::new();
+
+        if !self.key.matches(description.key_schema().unwrap_or_default()) {
+            drift.push(Drift::KeySchema);
+        }
+
+        let deployed_attributes = description.attribute_definitions().unwrap_or_default();
+        for attribute in self.attribute_definitions() {
+            if !deployed_attributes.contains(&attribute) {
+                drift.push(Drift::AttributeDefinition(attribute.attribute_name().unwrap_or_default().to_string()));
+            }
+        }
+
+        let deployed_indexes = description.global_secondary_indexes().unwrap_or_default();
+        for index in &self.global_secondary_indexes {
+            match deployed_indexes.iter().find(|i| i.index_name() == Some(index.name)) {
+                Some(deployed) => {
+                    let projection_matches = deployed.projection() == Some(&index.projection.sdk());
+                    if !index.key.matches(deployed.key_schema().unwrap_or_default()) || !projection_matches {
+                        drift.push(Drift::ChangedIndex(index.name.to_string()));
+                    }
+                }
+                None => drift.push(Drift::MissingIndex(index.name.to_string())),
+            }
+        }
+        for deployed in deployed_indexes {
+            let name = deployed.index_name().unwrap_or_default();
+            if !self.global_secondary_indexes.iter().any(|i| i.name == name) {
+                drift.push(Drift::UnexpectedIndex(name.to_string()));
+            }
+        }
+
+        // tables without a billing mode summary have only ever been provisioned
+        let deployed_billing_mode = description.billing_mode_summary()
+            .and_then(|summary| summary.billing_mode())
+            .cloned()
+            .unwrap_or(SdkBillingMode::Provisioned);
+        let billing_matches = match self.billing_mode {
+            BillingMode::PayPerRequest => deployed_billing_mode == SdkBillingMode::PayPerRequest,
+            BillingMode::Provisioned { read_capacity, write_capacity } => {
+                let throughput = description.provisioned_throughput();
+                deployed_billing_mode == SdkBillingMode::Provisioned
+                    && throughput.and_then(|t| t.read_capacity_units()) == Some(read_capacity)
+                    && throughput.and_then(|t| t.write_capacity_units()) == Some(write_capacity)
+            }
+        };
+        if !billing_matches {
+            drift.push(Drift::BillingMode);
+        }
+
+        if self.ttl_attribute != ttl_attribute {
+            drift.push(Drift::TimeToLive {
+                expected: self.ttl_attribute.map(String::from),
+                actual: ttl_attribute.map(String::from),
+            });
+        }
+
+        drift
+    }
+}
+
+/// A difference between a [`TableSchema`] and the deployed table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Drift {
+    KeySchema,
+    AttributeDefinition(String),
+    MissingIndex(String),
+    ChangedIndex(String),
+    UnexpectedIndex(String),
+    BillingMode,
+    TimeToLive { expected: Option<String>, actual: Option<String> },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::KeySchema => write!(f, "key schema differs"),
+            Drift::AttributeDefinition(name) => write!(f, "attribute definition for {name} missing or has the wrong type"),
+            Drift::MissingIndex(name) => write!(f, "global secondary index {name} is missing"),
+            Drift::ChangedIndex(name) => write!(f, "global secondary index {name} has a different key or projection"),
+            Drift::UnexpectedIndex(name) => write!(f, "global secondary index {name} is not in the schema"),
+            Drift::BillingMode => write!(f, "billing mode or provisioned throughput differs"),
+            Drift::TimeToLive { expected, actual } => write!(
+                f, "ttl attribute is {} but should be {}",
+                actual.as_deref().unwrap_or("disabled"),
+                expected.as_deref().unwrap_or("disabled")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::model::{BillingModeSummary, TableDescription};
+
+    use super::*;
+
+    fn test_schema() -> TableSchema {
+        TableSchema::new("test", KeySchema::new(
+            KeyAttribute::new("id", AttributeType::String),
+            Some(KeyAttribute::new("timestamp", AttributeType::Number)),
+        ))
+            .with_ttl_attribute("expiresAt")
+    }
+
+    fn deployed(schema: &TableSchema) -> TableDescription {
+        TableDescription::builder()
+            .table_name(schema.name())
+            .set_key_schema(Some(schema.key_schema()))
+            .set_attribute_definitions(Some(schema.attribute_definitions()))
+            .billing_mode_summary(BillingModeSummary::builder().billing_mode(SdkBillingMode::PayPerRequest).build())
+            .build()
+    }
+
+    #[test]
+    fn test_no_drift() {
+        let schema = test_schema();
+        assert!(schema.drift(&deployed(&schema), Some("expiresAt")).is_empty());
+    }
+
+    #[test]
+    fn test_drift() {
+        let schema = test_schema();
+        let description = deployed(&TableSchema::new("test", KeySchema::new(
+            KeyAttribute::new("id", AttributeType::String),
+            None,
+        )));
+        let drift = schema.drift(&description, None);
+        assert!(drift.contains(&Drift::KeySchema));
+        assert!(drift.contains(&Drift::AttributeDefinition("timestamp".to_string())));
+        assert!(drift.contains(&Drift::TimeToLive { expected: Some("expiresAt".to_string()), actual: None }));
+        assert!(!drift.contains(&Drift::BillingMode));
+    }
+
+    #[test]
+    fn test_registry_names_unique() {
+        let tables = tables();
+        for (i, table) in tables.iter().enumerate() {
+            assert!(tables.iter().skip(i + 1).all(|t| t.name() != table.name()), "duplicate table {}", table.name());
+        }
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::fmt;
+
+use aws_sdk_dynamodb::error::{TransactWriteItemsError, TransactWriteItemsErrorKind};
+use aws_sdk_dynamodb::model::{CancellationReason as SdkCancellationReason, ConditionCheck, Delete, Put, TransactWriteItem, Update};
+use aws_sdk_dynamodb::types::SdkError;
+
+use crate::database::{Database, Error};
+
+/// The most items DynamoDB accepts in one TransactWriteItems call.
+pub const MAX_TRANSACTION_ITEMS: usize = 100;
+
+/// Several writes that either all succeed or all fail.
+///
+/// The table modules build the individual operations, e.g.
+/// `Transaction::new().put(LoginsTable::put_login(database, user_id)).delete(NoncesTable::delete_nonce(database, device_id))`.
+#[derive(Default)]
+pub struct Transaction {
+    items: Vec<TransactWriteItem>,
+}
+
+impl Transaction {
+
+    pub fn new() -> Transaction {
+        Transaction { items: Vec::new() }
+    }
+
+    pub fn put(mut self, put: Put) -> Transaction {
+        self.items.push(TransactWriteItem::builder().put(put).build());
+        self
+    }
+    pub fn update(mut self, update: Update) -> Transaction {
+        self.items.push(TransactWriteItem::builder().update(update).build());
+        self
+    }
+    pub fn delete(mut self, delete: Delete) -> Transaction {
+        self.items.push(TransactWriteItem::builder().delete(delete).build());
+        self
+    }
+    /// Fail the whole transaction unless the condition holds, without writing the item.
+    pub fn condition_check(mut self, condition_check: ConditionCheck) -> Transaction {
+        self.items.push(TransactWriteItem::builder().condition_check(condition_check).build());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub async fn commit(self, database: &Database) -> Result<(), Error> {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        if self.items.len() > MAX_TRANSACTION_ITEMS {
+            return Err(Error::TransactionTooLarge(self.items.len()));
+        }
+
+        match database.client()
+            .transact_write_items()
+            .set_transact_items(Some(self.items))
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(map_error(e))
+        }
+    }
+}
+
+/// Why one item caused a transaction to be cancelled, in the order the items were added.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CancellationReason {
+    ConditionalCheckFailed,
+    ItemCollectionSizeLimitExceeded,
+    TransactionConflict,
+    ProvisionedThroughputExceeded,
+    ThrottlingError,
+    ValidationError(String),
+    Other(String),
+}
+
+impl CancellationReason {
+    /// `None` when DynamoDB reports the item as fine.
+    fn from_sdk(reason: &SdkCancellationReason) -> Option<CancellationReason> {
+        let message = reason.message().unwrap_or_default().to_string();
+        match reason.code() {
+            None | Some("None") => None,
+            Some("ConditionalCheckFailed") => Some(CancellationReason::ConditionalCheckFailed),
+            Some("ItemCollectionSizeLimitExceeded") => Some(CancellationReason::ItemCollectionSizeLimitExceeded),
+            Some("TransactionConflict") => Some(CancellationReason::TransactionConflict),
+            Some("ProvisionedThroughputExceeded") => Some(CancellationReason::ProvisionedThroughputExceeded),
+            Some("ThrottlingError") => Some(CancellationReason::ThrottlingError),
+            Some("ValidationError") => Some(CancellationReason::ValidationError(message)),
+            Some(code) => Some(CancellationReason::Other(format!("{code}: {message}"))),
+        }
+    }
+}
+
+/// A cancelled item and its position in the transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ItemError {
+    index: usize,
+    reason: CancellationReason,
+}
+
+impl ItemError {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn reason(&self) -> &CancellationReason {
+        &self.reason
+    }
+}
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "item {}: {:?}", self.index, self.reason)
+    }
+}
+
+fn map_error(error: SdkError<TransactWriteItemsError>) -> Error {
+    if let SdkError::ServiceError { err, .. } = &error {
+        if let TransactWriteItemsErrorKind::TransactionCanceledException(cancelled) = &err.kind {
+            let reasons = cancelled.cancellation_reasons().unwrap_or_default();
+            return Error::TransactionCanceled(item_errors(reasons));
+        }
+    }
+    Error::TransactWriteItems(error)
+}
+
+fn item_errors(reasons: &[SdkCancellationReason]) -> Vec<ItemError> {
+    reasons.iter()
+        .enumerate()
+        .filter_map(|(index, reason)| CancellationReason::from_sdk(reason).map(|reason| ItemError { index, reason }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::model::CancellationReason as SdkCancellationReason;
+
+    use crate::database::transaction::{CancellationReason, item_errors};
+
+    #[test]
+    fn test_item_errors() {
+        let reasons = vec![
+            SdkCancellationReason::builder().code("None").build(),
+            SdkCancellationReason::builder().code("ConditionalCheckFailed").message("The conditional request failed").build(),
+            SdkCancellationReason::builder().code("None").build(),
+            SdkCancellationReason::builder().code("TransactionConflict").build(),
+        ];
+        let errors = item_errors(&reasons);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].index(), 1);
+        assert_eq!(errors[0].reason(), &CancellationReason::ConditionalCheckFailed);
+        assert_eq!(errors[1].index(), 3);
+        assert_eq!(errors[1].reason(), &CancellationReason::TransactionConflict);
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Optimistic concurrency for records edited from several devices.
+//!
+//! A versioned item carries a numeric [`VERSION_ATTRIBUTE`]. Writes state the version they were based on, succeed only
+//! if the item is still at that version, and increment it. A stale write fails with
+//! [`Error::VersionConflict`] holding the item as it is now. An item written before it was versioned has no version
+//! and is written as if it were new.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_dynamodb::types::SdkError;
+
+use crate::database::{Database, Error, Item, TableSchema};
+use crate::database::schema::KeySchema;
+
+pub const VERSION_ATTRIBUTE: &str = "version";
+
+/// The version of an item, or `None` if it has never been written with one.
+pub fn version_of(item: &Item) -> Option<u64> {
+    match item.get(VERSION_ATTRIBUTE) {
+        Some(AttributeValue::N(n)) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Set `attributes` on the item at `key` in `table`, provided it is at `expected_version`. Returns the new version.
+///
+/// `expected_version` is `None` for an item that doesn't exist yet or was written before it was versioned.
+pub async fn update_versioned(
+    database: &Database,
+    table: &TableSchema,
+    key: Item,
+    attributes: Vec<(&str, AttributeValue)>,
+    expected_version: Option<u64>,
+) -> Result<u64, Error> {
+    let next_version = expected_version.map_or(1, |v| v + 1);
+    let condition = Condition::new(expected_version);
+
+    let mut names = condition.names;
+    let mut values = condition.values;
+    names.insert("#version".to_string(), VERSION_ATTRIBUTE.to_string());
+    values.insert(":next_version".to_string(), AttributeValue::N(next_version.to_string()));
+    let mut assignments = vec!["#version = :next_version".to_string()];
+    for (i, (name, value)) in attributes.into_iter().enumerate() {
+        names.insert(format!("#a{i}"), name.to_string());
+        values.insert(format!(":a{i}"), value);
+        assignments.push(format!("#a{i} = :a{i}"));
+    }
+
+    let table_name = database.table_name(table.name());
+    match database.client()
+        .update_item()
+        .table_name(&table_name)
+        .set_key(Some(key.clone()))
+        .update_expression(format!("SET {}", assignments.join(", ")))
+        .condition_expression(condition.expression)
+        .set_expression_attribute_names(Some(names))
+        .set_expression_attribute_values(Some(values))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(next_version),
+        Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => {
+            Err(conflict(database, &table_name, key).await)
+        }
+        Err(e) => Err(Error::UpdateItem(e))
+    }
+}
+
+/// Replace the whole item in `table`, provided the stored one is at `expected_version`. Returns the new version.
+///
+/// `expected_version` is `None` for an item that doesn't exist yet or was written before it was versioned.
+pub async fn put_versioned(
+    database: &Database,
+    table: &TableSchema,
+    mut item: Item,
+    expected_version: Option<u64>,
+) -> Result<u64, Error> {
+    let next_version = expected_version.map_or(1, |v| v + 1);
+    item.insert(VERSION_ATTRIBUTE.to_string(), AttributeValue::N(next_version.to_string()));
+    let key = key_of(table.key(), &item);
+    let condition = Condition::new(expected_version);
+
+    let table_name = database.table_name(table.name());
+    let values = Some(condition.values).filter(|values| !values.is_empty());
+    match database.client()
+        .put_item()
+        .table_name(&table_name)
+        .set_item(Some(item))
+        .condition_expression(condition.expression)
+        .set_expression_attribute_names(Some(condition.names))
+        .set_expression_attribute_values(values)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(next_version),
+        Err(SdkError::ServiceError { err, .. }) if err.is_conditional_check_failed_exception() => {
+            Err(conflict(database, &table_name, key).await)
+        }
+        Err(e) => Err(Error::PutItem(e))
+    }
+}
+
+/// The condition for a write based on `expected_version`, with the names and values it refers to.
+#[derive(Debug, PartialEq)]
+struct Condition {
+    expression: String,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl Condition {
+    fn new(expected_version: Option<u64>) -> Condition {
+        match expected_version {
+            Some(version) => Condition {
+                expression: "#version = :expected_version".to_string(),
+                names: HashMap::from([("#version".to_string(), VERSION_ATTRIBUTE.to_string())]),
+                values: HashMap::from([(":expected_version".to_string(), AttributeValue::N(version.to_string()))]),
+            },
+            // matches both a missing item and one written before it was versioned
+            None => Condition {
+                expression: "attribute_not_exists(#version)".to_string(),
+                names: HashMap::from([("#version".to_string(), VERSION_ATTRIBUTE.to_string())]),
+                values: HashMap::new(),
+            },
+        }
+    }
+}
+
+/// Just the key attributes of `item`.
+fn key_of(key: &KeySchema, item: &Item) -> Item {
+    [Some(key.partition_key()), key.sort_key()].into_iter()
+        .flatten()
+        .filter_map(|attribute| item.get(attribute.name()).map(|value| (attribute.name().to_string(), value.clone())))
+        .collect()
+}
+
+async fn conflict(database: &Database, table_name: &str, key: Item) -> Error {
+    match database.client()
+        .get_item()
+        .table_name(table_name)
+        .set_key(Some(key))
+        .consistent_read(true)
+        .send()
+        .await
+    {
+        Ok(output) => Error::VersionConflict(output.item().cloned()),
+        Err(e) => Error::GetItem(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+
+    use crate::database::schema::{AttributeType, KeyAttribute, KeySchema};
+    use crate::database::versioned::{Condition, VERSION_ATTRIBUTE, key_of, version_of};
+
+    fn key_schema() -> KeySchema {
+        KeySchema::new(
+            KeyAttribute::new("userId", AttributeType::String),
+            Some(KeyAttribute::new("timestamp", AttributeType::Number)),
+        )
+    }
+
+    #[test]
+    fn test_version_of() {
+        assert_eq!(version_of(&HashMap::new()), None);
+        let item = HashMap::from([(VERSION_ATTRIBUTE.to_string(), AttributeValue::N("3".to_string()))]);
+        assert_eq!(version_of(&item), Some(3));
+    }
+
+    #[test]
+    fn test_legacy_item_condition() {
+        // an item written before versioning is updated with the version read from it
+        let legacy = HashMap::from([
+            ("userId".to_string(), AttributeValue::S("user".to_string())),
+            ("timestamp".to_string(), AttributeValue::N("1".to_string())),
+        ]);
+        let condition = Condition::new(version_of(&legacy));
+        assert_eq!(condition.expression, "attribute_not_exists(#version)");
+        assert_eq!(condition.names, HashMap::from([("#version".to_string(), VERSION_ATTRIBUTE.to_string())]));
+        assert!(condition.values.is_empty());
+    }
+
+    #[test]
+    fn test_expected_version_condition() {
+        let condition = Condition::new(Some(3));
+        assert_eq!(condition.expression, "#version = :expected_version");
+        assert_eq!(condition.names, HashMap::from([("#version".to_string(), VERSION_ATTRIBUTE.to_string())]));
+        assert_eq!(condition.values, HashMap::from([(":expected_version".to_string(), AttributeValue::N("3".to_string()))]));
+    }
+
+    #[test]
+    fn test_key_of() {
+        let item = HashMap::from([
+            ("userId".to_string(), AttributeValue::S("user".to_string())),
+            ("timestamp".to_string(), AttributeValue::N("1".to_string())),
+            ("deviceId".to_string(), AttributeValue::S("device".to_string())),
+        ]);
+        let key = key_of(&key_schema(), &item);
+        assert_eq!(key.len(), 2);
+        assert!(!key.contains_key("deviceId"));
+    }
+}
@@ -0,0 +1,296 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::fmt::Display;
+
+use lambda_http::http::header::{ALLOW, CONTENT_TYPE, HeaderName, HeaderValue};
+use lambda_http::http::HeaderMap;
+use lambda_http::http::StatusCode;
+use lambda_http::{Body, Response};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use tracing::{error, warn};
+
+use crate::database;
+use crate::database::pagination::item_to_json;
+use crate::http::{ErrorDetail, HttpErrorResponse, Rejection};
+
+/// Stable, machine readable error codes. Clients should branch on these rather than on messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    NotFound,
+    MethodNotAllowed,
+    MissingHeader,
+    MissingParameter,
+    InvalidHeader,
+    UnsupportedMediaType,
+    MalformedBody,
+    ValidationFailed,
+    InvalidPathParameters,
+    InvalidQueryParameters,
+    InvalidPaginationToken,
+    Unauthenticated,
+    VersionConflict,
+    IdempotencyKeyReused,
+    RequestInProgress,
+    UnsupportedVersion,
+    UpgradeRequired,
+    Internal,
+}
+
+/// A problem with one field of the request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct FieldError {
+    field: String,
+    message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> FieldError {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The error type of every handler. Converted into an [`HttpErrorResponse`] by the [`Router`](crate::http::Router).
+///
+/// `message` is public. Anything that might reveal internals (database errors, upstream responses) belongs in the
+/// diagnostic, which is logged with the request id and only returned to clients on verbose stages.
+#[derive(Error, Debug)]
+#[error("{code:?}: {message}")]
+pub struct ApiError {
+    status: u16,
+    code: ErrorCode,
+    message: String,
+    details: Vec<FieldError>,
+    request_id: Option<String>,
+    current: Option<Value>,
+    headers: HeaderMap,
+    diagnostic: Option<String>,
+}
+
+const INTERNAL_MESSAGE: &str = "An internal error occurred";
+
+impl ApiError {
+
+    pub fn new(status: u16, code: ErrorCode, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: Vec::new(),
+            request_id: None,
+            current: None,
+            headers: HeaderMap::new(),
+            diagnostic: None,
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> ApiError {
+        ApiError::new(500, ErrorCode::Internal, message)
+    }
+
+    /// 409 for [`database::Error::VersionConflict`], with the record as it is now so the client can merge and retry.
+    /// A record that can't be serialized is a 500 instead, since the client couldn't merge without it.
+    pub fn version_conflict<T: Serialize>(current: Option<T>) -> ApiError {
+        let current = match current.map(serde_json::to_value).transpose() {
+            Ok(current) => current,
+            Err(e) => return ApiError::internal(INTERNAL_MESSAGE).with_diagnostic(e),
+        };
+        let mut error = ApiError::new(409, ErrorCode::VersionConflict, "The record was modified by another request");
+        error.current = current;
+        error
+    }
+
+    pub fn with_details(mut self, details: Vec<FieldError>) -> ApiError {
+        self.details = details;
+        self
+    }
+    /// Internal detail about the cause, for the logs.
+    pub fn with_diagnostic(mut self, diagnostic: impl Display) -> ApiError {
+        self.diagnostic = Some(diagnostic.to_string());
+        self
+    }
+    pub fn with_request_id(mut self, request_id: Option<String>) -> ApiError {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Extra headers for the response, e.g. `Allow` for 405s.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> ApiError {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    pub fn details(&self) -> &Vec<FieldError> {
+        &self.details
+    }
+    pub fn diagnostic(&self) -> Option<&str> {
+        self.diagnostic.as_deref()
+    }
+
+    /// Log the error with its diagnostic and build the response, including the diagnostic only if `detail` is verbose.
+    pub fn into_response(self, detail: ErrorDetail) -> Response<Body> {
+        self.log();
+        let diagnostic = if detail.is_verbose() { self.diagnostic } else { None };
+
+        let body = HttpErrorResponse::new(self.code, self.message)
+            .with_details(self.details)
+            .with_request_id(self.request_id)
+            .with_current(self.current)
+            .with_diagnostic(diagnostic);
+        // the envelope only contains strings and string keyed maps, so this can't fail
+        let json = serde_json::to_string(&body).unwrap_or_default();
+
+        let mut response = Response::new(Body::from(json));
+        *response.headers_mut() = self.headers;
+        *response.status_mut() = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+
+    fn log(&self) {
+        let request_id = self.request_id.as_deref().unwrap_or_default();
+        let diagnostic = self.diagnostic.as_deref().unwrap_or_default();
+        if self.status >= 500 {
+            error!(requestId = request_id, code = ?self.code, diagnostic, "{}", self.message);
+        } else if self.diagnostic.is_some() {
+            warn!(requestId = request_id, code = ?self.code, diagnostic, "{}", self.message);
+        }
+    }
+}
+
+impl From<ApiError> for Response<Body> {
+    fn from(error: ApiError) -> Self {
+        error.into_response(ErrorDetail::Public)
+    }
+}
+
+impl From<Rejection> for ApiError {
+    fn from(rejection: Rejection) -> Self {
+        let status = rejection.status();
+        let message = rejection.to_string();
+        let (code, details) = match &rejection {
+            Rejection::NotFound(_) => (ErrorCode::NotFound, Vec::new()),
+            Rejection::MethodNotAllowed(_, _) => (ErrorCode::MethodNotAllowed, Vec::new()),
+            Rejection::MissingHeader(name) => (ErrorCode::MissingHeader, vec![FieldError::new(*name, "required")]),
+            Rejection::InvalidHeader(name) => (ErrorCode::InvalidHeader, vec![FieldError::new(*name, "invalid")]),
+            Rejection::UnsupportedMediaType => (ErrorCode::UnsupportedMediaType, Vec::new()),
+            Rejection::InvalidBodyEncoding | Rejection::MalformedJson(_) => (ErrorCode::MalformedBody, Vec::new()),
+            Rejection::InvalidJson(e) => (ErrorCode::ValidationFailed, field_errors(e)),
+            Rejection::InvalidPathParameters(_) => (ErrorCode::InvalidPathParameters, Vec::new()),
+            Rejection::InvalidQueryParameters(_) => (ErrorCode::InvalidQueryParameters, Vec::new()),
+            Rejection::Unauthenticated => (ErrorCode::Unauthenticated, Vec::new()),
+        };
+        let error = ApiError::new(status, code, message).with_details(details);
+        match rejection {
+            Rejection::MethodNotAllowed(_, allowed) => match HeaderValue::from_str(&allowed.join(", ")) {
+                Ok(value) => error.with_header(ALLOW, value),
+                Err(_) => error,
+            },
+            _ => error,
+        }
+    }
+}
+
+impl From<database::Error> for ApiError {
+    fn from(error: database::Error) -> Self {
+        match error {
+            database::Error::NotFound => ApiError::new(404, ErrorCode::NotFound, error.to_string()),
+            database::Error::InvalidCursor => ApiError::new(400, ErrorCode::InvalidPaginationToken, error.to_string()),
+            database::Error::VersionConflict(current) => ApiError::version_conflict(current.as_ref().map(item_to_json)),
+            _ => ApiError::internal(INTERNAL_MESSAGE).with_diagnostic(error),
+        }
+    }
+}
+
+impl From<lambda_http::Error> for ApiError {
+    fn from(error: lambda_http::Error) -> Self {
+        ApiError::internal(INTERNAL_MESSAGE).with_diagnostic(error)
+    }
+}
+
+/// Pull field names out of serde's messages, e.g. "missing field `deviceId` at line 1 column 2".
+fn field_errors(message: &str) -> Vec<FieldError> {
+    let field = message.split('`').nth(1);
+    match field {
+        Some(field) if message.starts_with("missing field") => vec![FieldError::new(field, "required")],
+        Some(field) if message.starts_with("unknown field") => vec![FieldError::new(field, "not allowed")],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+    use lambda_http::{Body, Response};
+    use serde_json::{Value, json};
+
+    use crate::database;
+    use crate::http::{ApiError, ErrorDetail, Rejection};
+
+    #[test]
+    fn test_envelope() {
+        let error = ApiError::from(Rejection::InvalidJson("missing field `deviceId` at line 1 column 2".to_string()))
+            .with_request_id(Some("request".to_string()));
+        let response = Response::<Body>::from(error);
+        assert_eq!(response.status(), 422);
+
+        let body: Value = serde_json::from_slice(response.body().as_ref()).expect("Body was not JSON");
+        assert_eq!(body["code"], "VALIDATION_FAILED");
+        assert_eq!(body["details"][0]["field"], "deviceId");
+        assert_eq!(body["requestId"], "request");
+        assert!(chrono::DateTime::parse_from_rfc3339(body["timestamp"].as_str().expect("Missing timestamp")).is_ok());
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let error = || ApiError::internal("Could not make a nonce").with_diagnostic("connection refused");
+
+        let body = |response: Response<Body>| -> Value {
+            serde_json::from_slice(response.body().as_ref()).expect("Body was not JSON")
+        };
+        let public = body(error().into_response(ErrorDetail::Public));
+        assert_eq!(public["message"], "Could not make a nonce");
+        assert!(public.get("diagnostic").is_none());
+
+        let verbose = body(error().into_response(ErrorDetail::Verbose));
+        assert_eq!(verbose["diagnostic"], "connection refused");
+    }
+
+    #[test]
+    fn test_version_conflict() {
+        let current = HashMap::from([
+            ("userId".to_string(), AttributeValue::S("abc".to_string())),
+            ("version".to_string(), AttributeValue::N("4".to_string())),
+        ]);
+        let response = Response::<Body>::from(ApiError::from(database::Error::VersionConflict(Some(current))));
+        assert_eq!(response.status(), 409);
+        let body: Value = serde_json::from_slice(response.body().as_ref()).expect("Body was not JSON");
+        assert_eq!(body["current"], json!({"userId": "abc", "version": 4}));
+    }
+}
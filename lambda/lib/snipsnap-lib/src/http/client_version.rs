@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Turning away app builds too old to talk to this backend.
+//!
+//! Clients identify themselves with `X-Client-Version: ios/142`. Builds that don't send it are recognized by the
+//! `User-Agent` URLSession sends by default, `SnipSnap/142 CFNetwork/1390 Darwin/22.0.0`, where `142` is the bundle
+//! version. Requests from anything else, like browsers, scripts and other apps, are let through.
+
+use std::cmp::Ordering;
+use std::env;
+use std::fmt::{Display, Formatter};
+
+use lambda_http::Request;
+
+use crate::http::{ApiError, ErrorCode, FromRequest, Rejection};
+use crate::http::extract::header;
+
+pub const CLIENT_VERSION_HEADER: &str = "X-Client-Version";
+pub const USER_AGENT_HEADER: &str = "User-Agent";
+/// The product name at the start of this app's `User-Agent`.
+const PRODUCT_NAME: &str = "SnipSnap";
+const MIN_IOS_BUILD_VARIABLE: &str = "SNIPSNAP_MIN_IOS_BUILD";
+const MIN_ANDROID_BUILD_VARIABLE: &str = "SNIPSNAP_MIN_ANDROID_BUILD";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Ios,
+    Android,
+}
+
+impl Platform {
+    fn parse(value: &str) -> Option<Platform> {
+        match value.to_ascii_lowercase().as_str() {
+            "ios" => Some(Platform::Ios),
+            "android" => Some(Platform::Android),
+            _ => None,
+        }
+    }
+}
+
+/// A build number like `142` or `1.4.2`, compared component by component.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Build(Vec<u32>);
+
+impl Build {
+    pub fn parse(value: &str) -> Option<Build> {
+        value.trim()
+            .split('.')
+            .map(|component| component.parse().ok())
+            .collect::<Option<Vec<u32>>>()
+            .map(Build)
+    }
+}
+
+impl Ord for Build {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // 1.4 == 1.4.0
+        let length = self.0.len().max(other.0.len());
+        let component = |build: &Build, i: usize| build.0.get(i).copied().unwrap_or(0);
+        (0..length)
+            .map(|i| component(self, i).cmp(&component(other, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Build {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Build {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let components: Vec<String> = self.0.iter().map(u32::to_string).collect();
+        write!(f, "{}", components.join("."))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientVersion {
+    platform: Platform,
+    build: Build,
+}
+
+impl ClientVersion {
+
+    /// From the `X-Client-Version` header, falling back to the `User-Agent`.
+    pub fn parse(client_version: Option<&str>, user_agent: Option<&str>) -> Option<ClientVersion> {
+        client_version.and_then(ClientVersion::parse_header)
+            .or_else(|| user_agent.and_then(ClientVersion::parse_user_agent))
+    }
+
+    fn parse_header(value: &str) -> Option<ClientVersion> {
+        let (platform, build) = value.split_once('/')?;
+        Some(ClientVersion {
+            platform: Platform::parse(platform.trim())?,
+            build: Build::parse(build)?,
+        })
+    }
+
+    fn parse_user_agent(value: &str) -> Option<ClientVersion> {
+        let platform = if value.contains("CFNetwork") || value.contains("Darwin") {
+            Platform::Ios
+        } else if value.contains("okhttp") || value.contains("Dalvik") {
+            Platform::Android
+        } else {
+            return None;
+        };
+        let (product, build) = value.split_whitespace().next()?.split_once('/')?;
+        if product != PRODUCT_NAME {
+            return None;
+        }
+        Some(ClientVersion {
+            platform,
+            build: Build::parse(build)?,
+        })
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+    pub fn build(&self) -> &Build {
+        &self.build
+    }
+}
+
+impl FromRequest for ClientVersion {
+    fn from_request(request: &Request) -> Result<Self, Rejection> {
+        ClientVersion::parse(header(request, CLIENT_VERSION_HEADER).ok(), header(request, USER_AGENT_HEADER).ok())
+            .ok_or(Rejection::MissingHeader(CLIENT_VERSION_HEADER))
+    }
+}
+
+/// The oldest build still supported on each platform. Platforms without one accept every build.
+#[derive(Clone, Debug, Default)]
+pub struct MinimumVersions {
+    ios: Option<Build>,
+    android: Option<Build>,
+}
+
+impl MinimumVersions {
+
+    /// Read `SNIPSNAP_MIN_IOS_BUILD` and `SNIPSNAP_MIN_ANDROID_BUILD`, both optional.
+    pub fn from_env() -> MinimumVersions {
+        let build = |variable| env::var(variable).ok().and_then(|value| Build::parse(&value));
+        MinimumVersions {
+            ios: build(MIN_IOS_BUILD_VARIABLE),
+            android: build(MIN_ANDROID_BUILD_VARIABLE),
+        }
+    }
+
+    pub fn with_minimum(mut self, platform: Platform, build: Build) -> MinimumVersions {
+        match platform {
+            Platform::Ios => self.ios = Some(build),
+            Platform::Android => self.android = Some(build),
+        }
+        self
+    }
+
+    pub fn minimum(&self, platform: Platform) -> Option<&Build> {
+        match platform {
+            Platform::Ios => self.ios.as_ref(),
+            Platform::Android => self.android.as_ref(),
+        }
+    }
+
+    /// Unrecognized clients are supported, since there is nothing to tell them.
+    pub fn is_supported(&self, client: Option<&ClientVersion>) -> bool {
+        match client.and_then(|client| self.minimum(client.platform).map(|minimum| (client, minimum))) {
+            Some((client, minimum)) => client.build >= *minimum,
+            None => true,
+        }
+    }
+
+    /// 426 [`ErrorCode::UpgradeRequired`] for unsupported builds, which the app answers with a forced update screen.
+    pub fn check(&self, client: Option<&ClientVersion>) -> Result<(), ApiError> {
+        match self.is_supported(client) {
+            true => Ok(()),
+            false => Err(ApiError::new(426, ErrorCode::UpgradeRequired, "This version of the app is no longer supported")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::client_version::{Build, ClientVersion, MinimumVersions, Platform};
+
+    #[test]
+    fn test_parse() {
+        let client = ClientVersion::parse(Some("ios/1.4.2"), None).expect("Failed to parse header");
+        assert_eq!(client.platform(), Platform::Ios);
+        assert_eq!(client.build().to_string(), "1.4.2");
+
+        let client = ClientVersion::parse(None, Some("SnipSnap/142 CFNetwork/1390 Darwin/22.0.0"))
+            .expect("Failed to parse user agent");
+        assert_eq!(client.platform(), Platform::Ios);
+        assert_eq!(client.build().to_string(), "142");
+
+        let safari = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko)";
+        assert_eq!(ClientVersion::parse(None, Some(safari)), None);
+        // another app on the same networking stack
+        assert_eq!(ClientVersion::parse(None, Some("Photos/3 CFNetwork/1390 Darwin/22.0.0")), None);
+    }
+
+    #[test]
+    fn test_minimum() {
+        let minimum = MinimumVersions::default().with_minimum(Platform::Ios, Build::parse("1.4").expect("Invalid build"));
+        let client = |header| ClientVersion::parse(Some(header), None);
+
+        assert!(minimum.is_supported(client("ios/1.4.0").as_ref()));
+        assert!(minimum.is_supported(client("ios/1.10").as_ref()));
+        assert!(!minimum.is_supported(client("ios/1.3.9").as_ref()));
+        assert!(minimum.is_supported(client("android/1").as_ref()));
+        assert!(minimum.is_supported(None));
+        assert_eq!(minimum.check(client("ios/1.3").as_ref()).map_err(|e| e.status()), Err(426));
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::env;
+
+use lambda_http::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, HeaderMap, HeaderName, HeaderValue, VARY,
+};
+use lambda_http::http::Method;
+use lambda_http::{Body, Response};
+
+use crate::{DEV_STAGE, STAGE_VARIABLE};
+use crate::http::HttpResponseBuilder;
+
+const ORIGINS_VARIABLE: &str = "SNIPSNAP_CORS_ORIGINS";
+
+/// The headers clients send besides the CORS-safelisted ones.
+pub const DEFAULT_ALLOWED_HEADERS: [&str; 7] = [
+    "Authorization", "Content-Type", "X-UserId", "X-DeviceId", "Accept-Version", "X-Client-Version",
+    "Idempotency-Key",
+];
+const DEFAULT_MAX_AGE: u32 = 600;
+
+/// Which origins may call the API from a browser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    /// Answered with `*`, which browsers never combine with credentials.
+    Any,
+    List(Vec<String>),
+}
+
+impl AllowedOrigins {
+    fn allows(&self, origin: &str) -> bool {
+        match self {
+            AllowedOrigins::Any => true,
+            AllowedOrigins::List(origins) => origins.iter().any(|o| o == origin),
+        }
+    }
+}
+
+/// Cross-origin policy applied by the [`Router`](crate::http::Router) to every response, and used to answer
+/// `OPTIONS` preflight requests for any path that has routes.
+///
+/// Listed origins are echoed back, with `Vary: Origin`. [`AllowedOrigins::Any`] is answered with `*` and never allows
+/// credentials, since that would let any site make requests with the user's cookies.
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    origins: AllowedOrigins,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: u32,
+}
+
+impl CorsPolicy {
+
+    pub fn new(origins: AllowedOrigins) -> CorsPolicy {
+        CorsPolicy {
+            origins,
+            headers: DEFAULT_ALLOWED_HEADERS.iter().map(|h| h.to_string()).collect(),
+            credentials: false,
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+
+    /// Read a comma separated list of origins from `SNIPSNAP_CORS_ORIGINS`, `*` for any. When it isn't set, the
+    /// `dev` stage allows any origin and every other stage allows none.
+    pub fn from_env() -> CorsPolicy {
+        let origins = match env::var(ORIGINS_VARIABLE).ok().filter(|s| !s.is_empty()) {
+            Some(origins) if origins.trim() == "*" => AllowedOrigins::Any,
+            Some(origins) => AllowedOrigins::List(origins.split(',').map(|o| o.trim().to_string()).collect()),
+            None if env::var(STAGE_VARIABLE).ok().as_deref() == Some(DEV_STAGE) => AllowedOrigins::Any,
+            None => AllowedOrigins::List(Vec::new()),
+        };
+        CorsPolicy::new(origins)
+    }
+
+    /// Also allow `header` in requests.
+    pub fn with_header(mut self, header: impl Into<String>) -> CorsPolicy {
+        self.headers.push(header.into());
+        self
+    }
+    /// Send `Access-Control-Allow-Credentials: true` to listed origins. Has no effect with [`AllowedOrigins::Any`].
+    pub fn with_credentials(mut self, credentials: bool) -> CorsPolicy {
+        self.credentials = credentials;
+        self
+    }
+    /// How long, in seconds, browsers may cache a preflight response.
+    pub fn with_max_age(mut self, max_age: u32) -> CorsPolicy {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn origins(&self) -> &AllowedOrigins {
+        &self.origins
+    }
+    pub fn headers(&self) -> &Vec<String> {
+        &self.headers
+    }
+
+    /// Add the CORS headers to `response` if `origin` is allowed.
+    pub fn apply(&self, origin: Option<&str>, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        if self.origins == AllowedOrigins::Any {
+            if origin.is_some() {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+            }
+            return;
+        }
+
+        // the handler may have varied on Origin already, e.g. with HttpResponseBuilder::cors_origin
+        let varies = headers.get_all(VARY).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|name| name.trim().eq_ignore_ascii_case("origin"));
+        if !varies {
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        let Some(origin) = origin.filter(|o| self.origins.allows(o)) else {
+            return;
+        };
+        let Ok(origin) = HeaderValue::from_str(origin) else {
+            return;
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+
+    /// 204 answering a preflight for a path served by `methods`, before [`CorsPolicy::apply`]. If the origin isn't
+    /// allowed the response has no CORS headers, which browsers treat as a refusal.
+    pub fn preflight(&self, origin: Option<&str>, methods: &[Method]) -> Response<Body> {
+        let mut response = HttpResponseBuilder::no_content().build();
+        if !origin.is_some_and(|o| self.origins.allows(o)) {
+            return response;
+        }
+
+        let methods = methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+        let headers = response.headers_mut();
+        insert(headers, ACCESS_CONTROL_ALLOW_METHODS, &methods);
+        insert(headers, ACCESS_CONTROL_ALLOW_HEADERS, &self.headers.join(", "));
+        insert(headers, ACCESS_CONTROL_MAX_AGE, &self.max_age.to_string());
+        response
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::http::Method;
+
+    use crate::http::HttpResponseBuilder;
+    use crate::http::cors::{AllowedOrigins, CorsPolicy};
+
+    #[test]
+    fn test_preflight() {
+        let policy = CorsPolicy::new(AllowedOrigins::List(vec!["https://snipsnap.app".to_string()]))
+            .with_credentials(true);
+
+        let origin = Some("https://snipsnap.app");
+        let mut response = policy.preflight(origin, &[Method::POST]);
+        policy.apply(origin, &mut response);
+        assert_eq!(response.status(), 204);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://snipsnap.app");
+        assert_eq!(response.headers()["access-control-allow-methods"], "POST");
+        assert_eq!(response.headers()["access-control-allow-credentials"], "true");
+        assert!(response.headers()["access-control-allow-headers"].to_str().unwrap_or_default().contains("X-DeviceId"));
+
+        let origin = Some("https://example.com");
+        let mut response = policy.preflight(origin, &[Method::POST]);
+        policy.apply(origin, &mut response);
+        assert!(response.headers().get("access-control-allow-origin").is_none());
+        assert!(response.headers().get("access-control-allow-methods").is_none());
+    }
+
+    #[test]
+    fn test_any_origin() {
+        let policy = CorsPolicy::new(AllowedOrigins::Any).with_credentials(true);
+        let mut response = HttpResponseBuilder::no_content().build();
+        policy.apply(Some("https://example.com"), &mut response);
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+        assert!(response.headers().get("access-control-allow-credentials").is_none(), "allowed credentials for any origin");
+    }
+
+    #[test]
+    fn test_vary_once() {
+        let policy = CorsPolicy::new(AllowedOrigins::List(vec!["https://snipsnap.app".to_string()]));
+        let mut response = HttpResponseBuilder::no_content().cors_origin("https://snipsnap.app").build();
+        policy.apply(Some("https://snipsnap.app"), &mut response);
+        assert_eq!(response.headers().get_all("vary").iter().count(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::env;
+
+use crate::{DEV_STAGE, STAGE_VARIABLE};
+
+const ERROR_DETAIL_VARIABLE: &str = "SNIPSNAP_ERROR_DETAIL";
+
+/// How much of an internal error reaches the client.
+///
+/// Internal diagnostics are always logged with the request id. Clients only ever see the public error code and
+/// message, unless this is [`ErrorDetail::Verbose`], in which case the diagnostic is included in the response too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorDetail {
+    #[default]
+    Public,
+    Verbose,
+}
+
+impl ErrorDetail {
+
+    /// Read `SNIPSNAP_ERROR_DETAIL` (`public` or `verbose`). When it isn't set, the `dev` stage is verbose and every
+    /// other stage is public.
+    pub fn from_env() -> ErrorDetail {
+        match env::var(ERROR_DETAIL_VARIABLE).ok().as_deref() {
+            Some("verbose") => ErrorDetail::Verbose,
+            Some("public") => ErrorDetail::Public,
+            _ => ErrorDetail::for_stage(env::var(STAGE_VARIABLE).ok().as_deref()),
+        }
+    }
+
+    pub fn for_stage(stage: Option<&str>) -> ErrorDetail {
+        match stage {
+            Some(DEV_STAGE) => ErrorDetail::Verbose,
+            _ => ErrorDetail::Public,
+        }
+    }
+
+    pub fn is_verbose(&self) -> bool {
+        *self == ErrorDetail::Verbose
+    }
+}
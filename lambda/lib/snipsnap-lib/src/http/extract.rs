@@ -0,0 +1,198 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Typed access to the parts of a request.
+//!
+//! ```ignore
+//! async fn handler(database: Arc<Database>, request: Request) -> Result<Response<Body>, ApiError> {
+//!     let Json(body) = Json::<GetNonceRequest>::from_request(&request)?;
+//!     let user = AuthenticatedUser::from_request(&request)?;
+//!     ...
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use lambda_http::request::RequestContext;
+use lambda_http::{Context, Request, RequestExt};
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+
+use crate::http::Rejection;
+
+pub trait FromRequest: Sized {
+    fn from_request(request: &Request) -> Result<Self, Rejection>;
+}
+
+/// A JSON body. Requests that declare any other content type are rejected with 415, syntax errors with 400, and
+/// bodies that don't match `T` with 422.
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &Request) -> Result<Self, Rejection> {
+        if let Some(content_type) = request.headers().get("content-type") {
+            let content_type = content_type.to_str().map_err(|_| Rejection::InvalidHeader("Content-Type"))?;
+            if !content_type.starts_with("application/json") {
+                return Err(Rejection::UnsupportedMediaType);
+            }
+        }
+
+        let decoded = std::str::from_utf8(request.body().as_ref()).map_err(|_| Rejection::InvalidBodyEncoding)?;
+        serde_json::from_str(decoded).map(Json).map_err(|e| match e.classify() {
+            Category::Data => Rejection::InvalidJson(e.to_string()),
+            _ => Rejection::MalformedJson(e.to_string()),
+        })
+    }
+}
+
+/// Parameters captured from the route pattern, e.g. `{userId}` in `/users/{userId}`, or set by API Gateway.
+pub struct Path<T>(pub T);
+
+/// Parameters matched by the [`Router`](crate::http::Router), stored in the request extensions.
+#[derive(Clone, Default)]
+pub(crate) struct RouteParameters(pub(crate) HashMap<String, String>);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(request: &Request) -> Result<Self, Rejection> {
+        // the router's parameters replace API Gateway's of the same name
+        let mut parameters: HashMap<String, String> = request.path_parameters()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        if let Some(route_parameters) = request.extensions().get::<RouteParameters>() {
+            parameters.extend(route_parameters.0.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+
+        let encoded = serde_urlencoded::to_string(&parameters)
+            .map_err(|e| Rejection::InvalidPathParameters(e.to_string()))?;
+        serde_urlencoded::from_str(&encoded)
+            .map(Path)
+            .map_err(|e| Rejection::InvalidPathParameters(e.to_string()))
+    }
+}
+
+/// The query string.
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(request: &Request) -> Result<Self, Rejection> {
+        serde_urlencoded::from_str(request.uri().query().unwrap_or_default())
+            .map(Query)
+            .map_err(|e| Rejection::InvalidQueryParameters(e.to_string()))
+    }
+}
+
+/// The caller as established by the authorizer, read from the authorizer context.
+pub struct AuthenticatedUser {
+    user_id: String,
+    device_id: String,
+}
+
+impl AuthenticatedUser {
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+}
+
+pub const USER_ID_CONTEXT_KEY: &str = "userId";
+pub const DEVICE_ID_CONTEXT_KEY: &str = "deviceId";
+
+impl FromRequest for AuthenticatedUser {
+    fn from_request(request: &Request) -> Result<Self, Rejection> {
+        match (authorizer_value(request, USER_ID_CONTEXT_KEY), authorizer_value(request, DEVICE_ID_CONTEXT_KEY)) {
+            (Some(user_id), Some(device_id)) => Ok(AuthenticatedUser {
+                user_id: user_id.to_string(),
+                device_id: device_id.to_string(),
+            }),
+            _ => Err(Rejection::Unauthenticated),
+        }
+    }
+}
+
+/// A string the lambda authorizer put in its context. Payload format 2.0 nests the context under `lambda`. Format 1.0
+/// events from REST APIs put it directly in the authorizer, so both places are checked there.
+fn authorizer_value<'a>(request: &'a Request, key: &str) -> Option<&'a str> {
+    let value = match request.extensions().get::<RequestContext>()? {
+        RequestContext::ApiGatewayV2(context) => context.authorizer.as_ref()?.lambda.get(key),
+        RequestContext::ApiGatewayV1(context) => context.authorizer.get(key)
+            .or_else(|| context.authorizer.get("lambda")?.get(key)),
+    };
+    value?.as_str()
+}
+
+/// A required header as a string.
+pub fn header<'a>(request: &'a Request, name: &'static str) -> Result<&'a str, Rejection> {
+    request.headers()
+        .get(name)
+        .ok_or(Rejection::MissingHeader(name))?
+        .to_str()
+        .map_err(|_| Rejection::InvalidHeader(name))
+}
+
+/// The API Gateway request id, falling back to the lambda invocation's, for correlating error responses with logs.
+pub fn request_id(request: &Request) -> Option<String> {
+    let api_gateway = match request.extensions().get::<RequestContext>() {
+        Some(RequestContext::ApiGatewayV2(context)) => context.request_id.clone(),
+        Some(RequestContext::ApiGatewayV1(context)) => context.request_id.clone(),
+        None => None,
+    };
+    api_gateway.or_else(|| request.extensions().get::<Context>().map(|context| context.request_id.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::{Body, Request};
+    use serde::Deserialize;
+
+    use crate::http::{AuthenticatedUser, FromRequest, Json, Query, Rejection};
+    use crate::http::extract::request_id;
+
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct Example {
+        deviceId: String,
+    }
+
+    fn request(content_type: &str, body: &str) -> Request {
+        lambda_http::http::Request::builder()
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .expect("Failed to build request")
+    }
+
+    #[test]
+    fn test_json() {
+        let Json(example) = Json::<Example>::from_request(&request("application/json", r#"{"deviceId":"abc"}"#))
+            .unwrap_or_else(|_| panic!("Failed to extract"));
+        assert_eq!(example.deviceId, "abc");
+
+        let status = |r: Result<Json<Example>, Rejection>| r.err().map(|e| e.status());
+        assert_eq!(status(Json::from_request(&request("text/plain", r#"{"deviceId":"abc"}"#))), Some(415));
+        assert_eq!(status(Json::from_request(&request("application/json", r#"{"deviceId":"#))), Some(400));
+        assert_eq!(status(Json::from_request(&request("application/json", r#"{"device":"abc"}"#))), Some(422));
+    }
+
+    #[test]
+    fn test_query() {
+        let request = lambda_http::http::Request::builder()
+            .uri("https://example.com/logins?deviceId=abc")
+            .body(Body::Empty)
+            .expect("Failed to build request");
+        let Query(example) = Query::<Example>::from_request(&request).unwrap_or_else(|_| panic!("Failed to extract"));
+        assert_eq!(example.deviceId, "abc");
+    }
+
+    #[test]
+    fn test_rest_api() {
+        let request = lambda_http::request::from_str(include_str!("../../tests/rest_request.json"))
+            .expect("Failed to parse request");
+        let user = AuthenticatedUser::from_request(&request).unwrap_or_else(|_| panic!("Failed to extract"));
+        assert_eq!(user.user_id(), "user");
+        assert_eq!(user.device_id(), "device");
+        assert_eq!(request_id(&request).as_deref(), Some("c6af9ac6-7b61-11e6-9a41-93e8deadbeef"));
+    }
+}
@@ -2,21 +2,78 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use serde::{Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
 
-#[derive(Serialize)]
+use crate::http::api_error::{ErrorCode, FieldError};
+
+/// The body of every error response.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "ErrorResponse")]
+#[allow(non_snake_case)]
 pub struct HttpErrorResponse {
-    timestamp: u128,
-    message: String
+    code: ErrorCode,
+    message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    details: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requestId: Option<String>,
+    #[schemars(with = "DateTime<Utc>")]
+    timestamp: String,
+    /// The record as it is now, for version conflicts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Value>,
+    /// Internal detail, only included on stages with verbose errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostic: Option<String>,
 }
 
 impl HttpErrorResponse {
 
-    pub fn new(message: String) -> HttpErrorResponse {
+    pub fn new(code: ErrorCode, message: String) -> HttpErrorResponse {
         HttpErrorResponse {
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).expect("Failed to get current time").as_millis(),
-            message
+            code,
+            message,
+            details: Vec::new(),
+            requestId: None,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            current: None,
+            diagnostic: None,
         }
     }
+
+    pub fn with_details(mut self, details: Vec<FieldError>) -> HttpErrorResponse {
+        self.details = details;
+        self
+    }
+    pub fn with_request_id(mut self, request_id: Option<String>) -> HttpErrorResponse {
+        self.requestId = request_id;
+        self
+    }
+    pub fn with_current(mut self, current: Option<Value>) -> HttpErrorResponse {
+        self.current = current;
+        self
+    }
+    pub fn with_diagnostic(mut self, diagnostic: Option<String>) -> HttpErrorResponse {
+        self.diagnostic = diagnostic;
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    pub fn details(&self) -> &Vec<FieldError> {
+        &self.details
+    }
+    pub fn request_id(&self) -> Option<&str> {
+        self.requestId.as_deref()
+    }
+    pub fn timestamp(&self) -> &str {
+        &self.timestamp
+    }
 }
@@ -0,0 +1,180 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use lambda_http::http::header::{
+    ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_TYPE, ETAG, HeaderName, HeaderValue, LOCATION, VARY,
+};
+use lambda_http::http::{HeaderMap, StatusCode};
+use lambda_http::{Body, Response};
+use serde::Serialize;
+
+use crate::http::ApiError;
+
+/// Builds responses without a way to fail: anything that goes wrong along the way, like a body that won't serialize
+/// or a header value that isn't valid, turns the result into a 500 [`ApiError`] envelope.
+///
+/// ```ignore
+/// HttpResponseBuilder::json(200, &body)
+///     .etag(&version.to_string())
+///     .cache_control("private, max-age=60")
+///     .build()
+/// ```
+pub struct HttpResponseBuilder {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Body,
+    error: Option<ApiError>,
+}
+
+impl HttpResponseBuilder {
+
+    pub fn new(status: u16) -> HttpResponseBuilder {
+        let mut builder = HttpResponseBuilder {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Body::Empty,
+            error: None,
+        };
+        match StatusCode::from_u16(status) {
+            Ok(status) => builder.status = status,
+            Err(e) => builder.fail(ApiError::internal("Could not build the response").with_diagnostic(e)),
+        }
+        builder
+    }
+
+    /// `body` as `application/json`.
+    pub fn json<T>(status: u16, body: &T) -> HttpResponseBuilder where T: ?Sized + Serialize {
+        let mut builder = HttpResponseBuilder::new(status);
+        match serde_json::to_string(body) {
+            Ok(json) => {
+                builder.body = Body::Text(json);
+                builder = builder.content_type("application/json");
+            },
+            Err(e) => builder.fail(ApiError::internal("Could not serialize the response").with_diagnostic(e)),
+        }
+        builder
+    }
+
+    /// 204 with no body.
+    pub fn no_content() -> HttpResponseBuilder {
+        HttpResponseBuilder::new(204)
+    }
+
+    /// Raw bytes. API Gateway receives these base64 encoded, with `isBase64Encoded` set.
+    pub fn binary(status: u16, body: Vec<u8>, content_type: &str) -> HttpResponseBuilder {
+        let mut builder = HttpResponseBuilder::new(status).content_type(content_type);
+        builder.body = Body::Binary(body);
+        builder
+    }
+
+    pub fn text(status: u16, body: impl Into<String>, content_type: &str) -> HttpResponseBuilder {
+        let mut builder = HttpResponseBuilder::new(status).content_type(content_type);
+        builder.body = Body::Text(body.into());
+        builder
+    }
+
+    /// A redirect to `location`, e.g. 302, 303 or 307.
+    pub fn redirect(status: u16, location: &str) -> HttpResponseBuilder {
+        HttpResponseBuilder::new(status).header(LOCATION, location)
+    }
+
+    /// Set a header, replacing any previous value.
+    pub fn header(mut self, name: HeaderName, value: &str) -> HttpResponseBuilder {
+        match HeaderValue::from_str(value) {
+            Ok(value) => {
+                self.headers.insert(name, value);
+            },
+            Err(e) => self.fail(ApiError::internal("Could not build the response").with_diagnostic(format!("{name}: {e}"))),
+        }
+        self
+    }
+
+    /// Add a value to a header without replacing the existing ones.
+    pub fn append_header(mut self, name: HeaderName, value: &str) -> HttpResponseBuilder {
+        match HeaderValue::from_str(value) {
+            Ok(value) => {
+                self.headers.append(name, value);
+            },
+            Err(e) => self.fail(ApiError::internal("Could not build the response").with_diagnostic(format!("{name}: {e}"))),
+        }
+        self
+    }
+
+    pub fn content_type(self, content_type: &str) -> HttpResponseBuilder {
+        self.header(CONTENT_TYPE, content_type)
+    }
+
+    /// A strong ETag. `tag` is quoted here, so pass e.g. the record's version.
+    pub fn etag(self, tag: &str) -> HttpResponseBuilder {
+        self.header(ETAG, &format!("\"{tag}\""))
+    }
+
+    pub fn cache_control(self, cache_control: &str) -> HttpResponseBuilder {
+        self.header(CACHE_CONTROL, cache_control)
+    }
+
+    /// Allow `origin` to read the response, varying caches on `Origin`.
+    pub fn cors_origin(self, origin: &str) -> HttpResponseBuilder {
+        self.header(ACCESS_CONTROL_ALLOW_ORIGIN, origin).append_header(VARY, "Origin")
+    }
+
+    pub fn build(self) -> Response<Body> {
+        if let Some(error) = self.error {
+            return error.into();
+        }
+        let mut response = Response::new(self.body);
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+
+    fn fail(&mut self, error: ApiError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl From<HttpResponseBuilder> for Response<Body> {
+    fn from(builder: HttpResponseBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lambda_http::Body;
+
+    use crate::http::HttpResponseBuilder;
+
+    #[test]
+    fn test_headers() {
+        let response = HttpResponseBuilder::json(200, &"body")
+            .etag("3")
+            .cache_control("no-store")
+            .build();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        assert_eq!(response.headers()["etag"], "\"3\"");
+        assert_eq!(response.headers()["cache-control"], "no-store");
+
+        let response = HttpResponseBuilder::no_content().build();
+        assert_eq!(response.status(), 204);
+        assert_eq!(response.body(), &Body::Empty);
+
+        let response = HttpResponseBuilder::redirect(303, "/logins").build();
+        assert_eq!(response.headers()["location"], "/logins");
+    }
+
+    #[test]
+    fn test_failure_is_500() {
+        // maps with non-string keys can't be JSON
+        let body = HashMap::from([((1, 2), "value")]);
+        let response = HttpResponseBuilder::json(200, &body).build();
+        assert_eq!(response.status(), 500);
+
+        let response = HttpResponseBuilder::json(200, &"body").etag("bad\nvalue").build();
+        assert_eq!(response.status(), 500);
+    }
+}
@@ -2,20 +2,17 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
-use lambda_http::{Body, Error, Response};
+use lambda_http::{Body, Response};
 use serde::Serialize;
-use serde_json;
+
+use crate::http::HttpResponseBuilder;
 
 pub struct HttpResponseGenerator {}
 
 impl HttpResponseGenerator {
 
-    pub fn response<T>(code: u16, body: &T) -> Result<Response<Body>, Error> where T: ?Sized + Serialize  {
-        let response = Response::builder()
-            .status(code)
-            .header("content-type", "application/json")
-            .body(serde_json::to_string(&body).expect("").into())
-            .map_err(Box::new)?;
-        Ok(response)
+    /// `body` as JSON, or a 500 envelope if it can't be serialized. Use [`HttpResponseBuilder`] for anything else.
+    pub fn response<T>(code: u16, body: &T) -> Response<Body> where T: ?Sized + Serialize  {
+        HttpResponseBuilder::json(code, body).build()
     }
 }
@@ -0,0 +1,264 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! `Idempotency-Key` support, so a POST retried over a flaky mobile connection doesn't do its work twice.
+//!
+//! The first request with a key runs its handler and its response is stored in the
+//! [`IdempotencyTable`](crate::database::IdempotencyTable). Repeats of that request get the stored response back with
+//! an `Idempotent-Replayed: true` header. A different request reusing the key is a 409, as is a repeat that arrives
+//! while the first is still running. Responses with a 5xx status are not stored, so those requests can be retried.
+//! Responses marked `Cache-Control: no-store`, like `/login`'s with its session token, are stored without their body,
+//! so credentials are never kept in the table. Replaying one tells the client the request succeeded, but not what it
+//! returned.
+//!
+//! A key is only held for a short lease while its request runs, so a lambda that times out or crashes before storing a
+//! response doesn't lock the key up for the whole TTL. A request that outlives its lease stores nothing, since the key
+//! may have been claimed again by then.
+//!
+//! Keys are scoped to the authenticated user, so one user can never be replayed another's response. Keys sent to
+//! unauthenticated routes like `/get-nonce` are scoped to the route only and shared by every client, so two devices
+//! picking the same key collide. Since the body is part of the request hash and carries the device id, the second
+//! device gets a 409 rather than the first one's response.
+
+use chrono::{Duration, Utc};
+use lambda_http::http::header::{CACHE_CONTROL, HeaderName, HeaderValue};
+use lambda_http::http::{Method, StatusCode};
+use lambda_http::{Body, Request, Response};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::database::{Database, Error, IdempotencyTable};
+use crate::database::idempotency_table::StoredResponse;
+use crate::http::{ApiError, AuthenticatedUser, ErrorCode, FromRequest};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_KEY_LENGTH: usize = 255;
+const DEFAULT_TTL_HOURS: i64 = 24;
+/// Longer than the handlers' function timeout.
+const DEFAULT_LEASE_SECONDS: i64 = 30;
+
+/// Stores responses to keyed requests, see [`Router::with_idempotency`](crate::http::Router::with_idempotency).
+pub struct Idempotency {
+    database: Database,
+    ttl: Duration,
+    lease: Duration,
+}
+
+/// A key held by the request that claimed it, identified by its hash.
+pub(crate) struct ClaimedKey {
+    key: String,
+    request_hash: String,
+}
+
+/// What to do with a request before its handler runs.
+pub(crate) enum Claim {
+    /// No key, or a method that is idempotent anyway.
+    Unkeyed,
+    /// The first request with this key, whose response should be stored under it.
+    Claimed(ClaimedKey),
+    /// A repeat, answered with the stored response.
+    Replay(Response<Body>),
+}
+
+impl Idempotency {
+
+    /// Responses are kept for 24 hours, and keys are held for 30 seconds while their request runs.
+    pub fn new(database: Database) -> Idempotency {
+        Idempotency {
+            database,
+            ttl: Duration::hours(DEFAULT_TTL_HOURS),
+            lease: Duration::seconds(DEFAULT_LEASE_SECONDS),
+        }
+    }
+
+    pub fn from_env() -> Result<Idempotency, Error> {
+        Ok(Idempotency::new(Database::from_env()?))
+    }
+
+    /// How long a key is remembered. Retries after that run the handler again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Idempotency {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long a request holds its key before storing a response. Should outlast the function timeout, after which a
+    /// retry runs the handler again.
+    pub fn with_lease(mut self, lease: Duration) -> Idempotency {
+        self.lease = lease;
+        self
+    }
+
+    /// Claim the request's key, or find the response to replay. `route` is the path template the request matched.
+    pub(crate) async fn claim(&self, route: &str, request: &Request) -> Result<Claim, ApiError> {
+        let Some(key) = idempotency_key(request)? else {
+            return Ok(Claim::Unkeyed);
+        };
+        let key = scoped_key(route, request, key);
+        let request_hash = request_hash(route, request);
+        let lease_expires_at = Utc::now() + self.lease;
+
+        let existing = IdempotencyTable::claim(&self.database, &key, &request_hash, lease_expires_at).await
+            .map_err(|e| ApiError::internal("Could not check the idempotency key").with_diagnostic(e))?;
+        let Some(record) = existing else {
+            return Ok(Claim::Claimed(ClaimedKey { key, request_hash }));
+        };
+        if record.request_hash() != request_hash {
+            return Err(ApiError::new(409, ErrorCode::IdempotencyKeyReused, "Idempotency-Key was already used for a different request"));
+        }
+        match record.response() {
+            Some(response) => Ok(Claim::Replay(replay(response))),
+            None => Err(ApiError::new(409, ErrorCode::RequestInProgress, "A request with this Idempotency-Key is still in progress")),
+        }
+    }
+
+    /// Store the response for a claimed key, or give the key up if the request failed on our side. Failures are only
+    /// logged, since the request itself has already happened.
+    pub(crate) async fn complete(&self, claimed: &ClaimedKey, response: &Response<Body>) {
+        let ClaimedKey { key, request_hash } = claimed;
+        let result = match response.status().is_server_error() {
+            true => IdempotencyTable::release(&self.database, key, request_hash).await,
+            false => IdempotencyTable::complete(&self.database, key, request_hash, &stored(response), Utc::now() + self.ttl).await,
+        };
+        if let Err(e) = result {
+            warn!(error = %e, "Could not store the response for an idempotency key");
+        }
+    }
+}
+
+/// The `Idempotency-Key` of a request whose method needs one.
+fn idempotency_key(request: &Request) -> Result<Option<&str>, ApiError> {
+    if !matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE) {
+        return Ok(None);
+    }
+    let Some(value) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => Ok(Some(key)),
+        _ => Err(ApiError::new(400, ErrorCode::InvalidHeader, format!("Idempotency-Key must be 1 to {MAX_KEY_LENGTH} visible ASCII characters"))),
+    }
+}
+
+/// `user/key` for authenticated requests, otherwise `anonymous route key`, which can't collide with a user id.
+fn scoped_key(route: &str, request: &Request, key: &str) -> String {
+    match AuthenticatedUser::from_request(request) {
+        Ok(user) => format!("{}/{key}", user.user_id()),
+        Err(_) => format!("anonymous {route} {key}"),
+    }
+}
+
+/// Identifies a request by method, route and body.
+fn request_hash(route: &str, request: &Request) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request.method().as_str());
+    hasher.update(b" ");
+    hasher.update(route);
+    hasher.update(b"\n");
+    hasher.update(request.body().as_ref());
+    base64::encode_config(hasher.finalize(), base64::URL_SAFE_NO_PAD)
+}
+
+/// The response to store, without the body if it must not be kept.
+fn stored(response: &Response<Body>) -> StoredResponse {
+    let headers = response.headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = match is_no_store(response) {
+        true => Vec::new(),
+        false => response.body().as_ref().to_vec(),
+    };
+    StoredResponse::new(response.status().as_u16(), headers, body)
+}
+
+fn is_no_store(response: &Response<Body>) -> bool {
+    response.headers()
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+fn replay(stored: &StoredResponse) -> Response<Body> {
+    let body = match stored.body().is_empty() {
+        true => Body::Empty,
+        false => match std::str::from_utf8(stored.body()) {
+            Ok(text) => Body::Text(text.to_string()),
+            Err(_) => Body::Binary(stored.body().to_vec()),
+        },
+    };
+    let mut response = Response::new(body);
+    *response.status_mut() = StatusCode::from_u16(stored.status()).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers() {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::{Body, Request};
+
+    use crate::http::HttpResponseBuilder;
+    use crate::http::idempotency::{idempotency_key, replay, request_hash, scoped_key, stored};
+
+    fn request(method: &str, key: Option<&str>, body: &str) -> Request {
+        let mut builder = lambda_http::http::Request::builder().method(method).uri("/get-nonce");
+        if let Some(key) = key {
+            builder = builder.header("idempotency-key", key);
+        }
+        builder.body(Body::from(body)).expect("Failed to build request")
+    }
+
+    #[test]
+    fn test_key() {
+        let key = |method, key| idempotency_key(&request(method, key, ""))
+            .map(|key| key.map(str::to_string))
+            .map_err(|e| e.status());
+        assert_eq!(key("POST", Some("abc")), Ok(Some("abc".to_string())));
+        assert_eq!(key("POST", None), Ok(None));
+        assert_eq!(key("GET", Some("abc")), Ok(None));
+        assert_eq!(key("POST", Some("")), Err(400));
+        assert_eq!(key("POST", Some(&"a".repeat(256))), Err(400));
+    }
+
+    #[test]
+    fn test_scoped_key() {
+        let request = request("POST", Some("abc"), "");
+        assert_eq!(scoped_key("/get-nonce", &request, "abc"), "anonymous /get-nonce abc");
+        assert_ne!(scoped_key("/get-nonce", &request, "abc"), scoped_key("/login", &request, "abc"));
+    }
+
+    #[test]
+    fn test_request_hash() {
+        let hash = |method, body| request_hash("/get-nonce", &request(method, Some("abc"), body));
+        assert_eq!(hash("POST", r#"{"deviceId":"a"}"#), hash("POST", r#"{"deviceId":"a"}"#));
+        assert_ne!(hash("POST", r#"{"deviceId":"a"}"#), hash("POST", r#"{"deviceId":"b"}"#));
+        assert_ne!(hash("POST", r#"{"deviceId":"a"}"#), hash("PUT", r#"{"deviceId":"a"}"#));
+    }
+
+    #[test]
+    fn test_replay() {
+        let response = HttpResponseBuilder::text(201, "created", "text/plain").etag("v1").build();
+        let replayed = replay(&stored(&response));
+        assert_eq!(replayed.status(), 201);
+        assert_eq!(replayed.body().as_ref(), b"created");
+        assert_eq!(replayed.headers()["etag"], response.headers()["etag"]);
+        assert_eq!(replayed.headers()["idempotent-replayed"], "true");
+    }
+
+    #[test]
+    fn test_no_store() {
+        let response = HttpResponseBuilder::json(200, &"session token").cache_control("private, no-store").build();
+        let stored = stored(&response);
+        assert_eq!(stored.status(), 200);
+        assert!(stored.body().is_empty(), "Stored a response that carries credentials");
+    }
+}
@@ -2,8 +2,32 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
+pub mod api_error;
+pub mod client_version;
+pub mod cors;
+pub mod diagnostics;
 pub mod http_error_response;
+pub mod http_response_builder;
 pub mod http_response_generator;
+pub mod extract;
+pub mod idempotency;
+pub mod openapi;
+pub mod rejection;
+pub mod router;
+pub mod session;
+pub mod versioning;
 
+pub use api_error::{ApiError, ErrorCode, FieldError};
+pub use client_version::{ClientVersion, MinimumVersions};
+pub use cors::{AllowedOrigins, CorsPolicy};
+pub use diagnostics::ErrorDetail;
 pub use http_error_response::HttpErrorResponse;
+pub use http_response_builder::HttpResponseBuilder;
 pub use http_response_generator::HttpResponseGenerator;
+pub use extract::{AuthenticatedUser, FromRequest, Json, Path, Query};
+pub use idempotency::Idempotency;
+pub use openapi::{OpenApiSpec, Strictness};
+pub use rejection::Rejection;
+pub use router::{Router, Versioned};
+pub use session::{MissingSessionSecret, Session, SessionTokens};
+pub use versioning::{ApiVersion, RequestedVersion};
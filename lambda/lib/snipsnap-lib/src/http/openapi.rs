@@ -0,0 +1,333 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Checks requests and responses against `openapi.yaml`.
+//!
+//! Only the parts of OpenAPI the spec actually uses are understood: `$ref` to components, `type`, `required`,
+//! `properties`, `items`, `enum`, `nullable`, `oneOf`/`anyOf` and the `date-time` format, plus required header and
+//! query parameters.
+
+use chrono::DateTime;
+use lambda_http::{Body, Request, Response};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::http::{ApiError, ErrorCode, FieldError};
+
+#[derive(Error, Debug)]
+pub enum SpecError {
+    #[error("Invalid OpenAPI document: {0}")]
+    Parse(#[from] serde_yaml::Error),
+}
+
+/// Whether properties the schema doesn't declare are errors.
+///
+/// Requests are validated leniently, like serde deserializes them. Responses and the tests that compare handler types
+/// to the spec are strict, so that a field added in code but not in the spec is caught.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strictness {
+    Lenient,
+    Strict,
+}
+
+pub struct OpenApiSpec {
+    document: Value,
+}
+
+impl OpenApiSpec {
+
+    pub fn from_yaml(yaml: &str) -> Result<OpenApiSpec, SpecError> {
+        Ok(OpenApiSpec { document: serde_yaml::from_str(yaml)? })
+    }
+
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    /// `components/schemas/{name}`.
+    pub fn schema(&self, name: &str) -> Option<&Value> {
+        self.document.get("components")?.get("schemas")?.get(name)
+    }
+
+    /// The operation for a path template as written in the spec, e.g. `/users/{userId}`.
+    pub fn operation(&self, method: &str, path: &str) -> Option<&Value> {
+        self.document.get("paths")?.get(path)?.get(method.to_lowercase())
+    }
+
+    /// Check `value` against the named component schema.
+    pub fn validate_schema(&self, name: &str, value: &Value, strictness: Strictness) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        match self.schema(name) {
+            Some(schema) => self.validate(schema, value, "", strictness, &mut errors),
+            None => errors.push(FieldError::new(name, "no such schema")),
+        }
+        errors
+    }
+
+    /// Check the required header and query parameters and the JSON body of a request to the route `path`. Routes the
+    /// spec doesn't describe pass.
+    pub fn validate_request(&self, path: &str, request: &Request) -> Result<(), ApiError> {
+        let Some(operation) = self.operation(request.method().as_str(), path) else {
+            return Ok(());
+        };
+
+        let query: Vec<(String, String)> = serde_urlencoded::from_str(request.uri().query().unwrap_or_default())
+            .unwrap_or_default();
+        let mut missing = Vec::new();
+        let mut missing_query = false;
+        for parameter in operation.get("parameters").and_then(Value::as_array).into_iter().flatten() {
+            let parameter = self.resolve(parameter);
+            if parameter.get("required") != Some(&Value::Bool(true)) {
+                continue;
+            }
+            let Some(name) = parameter.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            let present = match parameter.get("in").and_then(Value::as_str) {
+                Some("header") => request.headers().contains_key(name),
+                Some("query") => {
+                    let present = query.iter().any(|(k, _)| k == name);
+                    missing_query |= !present;
+                    present
+                }
+                _ => true,
+            };
+            if !present {
+                missing.push(FieldError::new(name, "required"));
+            }
+        }
+        if !missing.is_empty() {
+            let code = match missing_query {
+                true => ErrorCode::MissingParameter,
+                false => ErrorCode::MissingHeader,
+            };
+            return Err(ApiError::new(400, code, "Missing required parameters").with_details(missing));
+        }
+
+        let Some(request_body) = operation.get("requestBody").map(|b| self.resolve(b)) else {
+            return Ok(());
+        };
+        let body = request.body().as_ref();
+        if body.is_empty() {
+            return match request_body.get("required") {
+                // 400 like the Json extractor gives an empty body
+                Some(Value::Bool(true)) => Err(ApiError::new(400, ErrorCode::MalformedBody, "Missing request body")),
+                _ => Ok(()),
+            };
+        }
+        let Some(schema) = json_schema(request_body) else {
+            return Ok(());
+        };
+        let Ok(value) = serde_json::from_slice::<Value>(body) else {
+            // left to the Json extractor, which knows whether this route wants JSON at all
+            return Ok(());
+        };
+
+        let mut errors = Vec::new();
+        self.validate(schema, &value, "", Strictness::Lenient, &mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ApiError::new(422, ErrorCode::ValidationFailed, "Request body does not match the API specification")
+                .with_details(errors)),
+        }
+    }
+
+    /// Check a JSON response from the route `path` against the documented response for its status.
+    pub fn validate_response(&self, method: &str, path: &str, response: &Response<Body>) -> Vec<FieldError> {
+        let Some(operation) = self.operation(method, path) else {
+            return Vec::new();
+        };
+        let status = response.status().as_u16().to_string();
+        let Some(documented) = operation.get("responses").and_then(|r| r.get(&status).or_else(|| r.get("default"))) else {
+            return vec![FieldError::new("status", format!("{status} is not documented"))];
+        };
+        let Some(schema) = json_schema(self.resolve(documented)) else {
+            return Vec::new();
+        };
+
+        let mut errors = Vec::new();
+        match serde_json::from_slice::<Value>(response.body().as_ref()) {
+            Ok(value) => self.validate(schema, &value, "", Strictness::Strict, &mut errors),
+            Err(_) => errors.push(FieldError::new("body", "not JSON")),
+        }
+        errors
+    }
+
+    fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str).and_then(|r| r.strip_prefix('#')) {
+            Some(pointer) => self.document.pointer(pointer).unwrap_or(&Value::Null),
+            None => schema,
+        }
+    }
+
+    fn validate(&self, schema: &Value, value: &Value, path: &str, strictness: Strictness, errors: &mut Vec<FieldError>) {
+        let schema = self.resolve(schema);
+
+        if value.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+            return;
+        }
+        for combinator in ["oneOf", "anyOf"] {
+            if let Some(options) = schema.get(combinator).and_then(Value::as_array) {
+                let matches = options.iter().any(|option| {
+                    let mut option_errors = Vec::new();
+                    self.validate(option, value, path, strictness, &mut option_errors);
+                    option_errors.is_empty()
+                });
+                if !matches {
+                    errors.push(FieldError::new(field(path), format!("matches none of {combinator}")));
+                }
+                return;
+            }
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                errors.push(FieldError::new(field(path), "not one of the allowed values"));
+            }
+        }
+
+        let expected = schema.get("type").and_then(Value::as_str);
+        let matches = match expected {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            _ => true,
+        };
+        if !matches {
+            errors.push(FieldError::new(field(path), format!("expected {}", expected.unwrap_or_default())));
+            return;
+        }
+
+        match value {
+            Value::String(string) if schema.get("format").and_then(Value::as_str) == Some("date-time")
+                && DateTime::parse_from_rfc3339(string).is_err() => {
+                errors.push(FieldError::new(field(path), "expected an RFC 3339 date-time"));
+            },
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        self.validate(item_schema, item, &format!("{path}[{i}]"), strictness, errors);
+                    }
+                }
+            },
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+                    if let Some(required) = required.as_str() {
+                        if !object.contains_key(required) {
+                            errors.push(FieldError::new(join(path, required), "required"));
+                        }
+                    }
+                }
+                for (key, value) in object {
+                    match properties.and_then(|p| p.get(key)) {
+                        Some(property) => self.validate(property, value, &join(path, key), strictness, errors),
+                        None if strictness == Strictness::Strict && properties.is_some() =>
+                            errors.push(FieldError::new(join(path, key), "not in the specification")),
+                        None => {}
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+fn json_schema(body: &Value) -> Option<&Value> {
+    body.get("content")?.get("application/json")?.get("schema")
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_string(),
+        false => format!("{path}.{key}"),
+    }
+}
+
+fn field(path: &str) -> &str {
+    match path.is_empty() {
+        true => "body",
+        false => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::Body;
+    use serde_json::json;
+
+    use crate::http::ErrorCode;
+    use crate::http::openapi::{OpenApiSpec, Strictness};
+
+    const SPEC: &str = include_str!("../../../../../openapi.yaml");
+
+    fn spec() -> OpenApiSpec {
+        OpenApiSpec::from_yaml(SPEC).expect("Failed to parse openapi.yaml")
+    }
+
+    #[test]
+    fn test_schema() {
+        let spec = spec();
+        assert!(spec.validate_schema("GetNonceResponse", &json!({"nonce": "abc"}), Strictness::Strict).is_empty());
+
+        let errors = spec.validate_schema("GetNonceResponse", &json!({"nonce": 1, "extra": true}), Strictness::Strict);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field()).collect();
+        assert_eq!(fields, vec!["nonce", "extra"]);
+        assert_eq!(spec.validate_schema("GetNonceResponse", &json!({"extra": true}), Strictness::Lenient).len(), 1);
+    }
+
+    #[test]
+    fn test_request() {
+        let spec = spec();
+        let request = |body: &str| lambda_http::http::Request::builder()
+            .method("POST")
+            .uri("/get-nonce")
+            .body(Body::from(body))
+            .expect("Failed to build request");
+
+        assert!(spec.validate_request("/get-nonce", &request(r#"{"deviceId":"abc"}"#)).is_ok());
+        let error = spec.validate_request("/get-nonce", &request(r#"{"deviceId":1}"#)).expect_err("Should not validate");
+        assert_eq!(error.status(), 422);
+        assert_eq!(error.details()[0].field(), "deviceId");
+        let error = spec.validate_request("/get-nonce", &request("")).expect_err("Should not validate");
+        assert_eq!(error.status(), 400);
+        assert_eq!(error.code(), ErrorCode::MalformedBody);
+
+        let login = lambda_http::http::Request::builder()
+            .method("POST")
+            .uri("/login")
+            .header("Authorization", "Bearer token")
+            .body(Body::Empty)
+            .expect("Failed to build request");
+        let error = spec.validate_request("/login", &login).expect_err("Should not validate");
+        assert_eq!(error.status(), 400);
+    }
+
+    #[test]
+    fn test_missing_parameter() {
+        let spec = OpenApiSpec::from_yaml(r#"
+paths:
+  /logins:
+    get:
+      parameters:
+      - name: limit
+        in: query
+        required: true
+        schema:
+          type: string
+"#).expect("Failed to parse spec");
+        let request = |uri: &str| lambda_http::http::Request::builder()
+            .uri(uri)
+            .body(Body::Empty)
+            .expect("Failed to build request");
+
+        assert!(spec.validate_request("/logins", &request("/logins?limit=10")).is_ok());
+        let error = spec.validate_request("/logins", &request("/logins")).expect_err("Should not validate");
+        assert_eq!(error.status(), 400);
+        assert_eq!(error.code(), ErrorCode::MissingParameter);
+        assert_eq!(error.details()[0].field(), "limit");
+    }
+}
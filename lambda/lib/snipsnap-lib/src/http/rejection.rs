@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use thiserror::Error;
+
+/// Why a request could not be routed or its parts extracted.
+///
+/// Handlers can return these with `?`; they convert into an [`ApiError`](crate::http::ApiError).
+#[derive(Error, Debug)]
+pub enum Rejection {
+    #[error("No route for {0}")]
+    NotFound(String),
+    #[error("Method {0} not allowed")]
+    MethodNotAllowed(String, Vec<String>),
+    #[error("Missing {0} header")]
+    MissingHeader(&'static str),
+    #[error("Invalid {0} header")]
+    InvalidHeader(&'static str),
+    #[error("Expected content type application/json")]
+    UnsupportedMediaType,
+    #[error("Body is not valid UTF-8")]
+    InvalidBodyEncoding,
+    #[error("Malformed JSON body: {0}")]
+    MalformedJson(String),
+    #[error("Invalid JSON body: {0}")]
+    InvalidJson(String),
+    #[error("Invalid path parameters: {0}")]
+    InvalidPathParameters(String),
+    #[error("Invalid query parameters: {0}")]
+    InvalidQueryParameters(String),
+    #[error("Request was not authenticated")]
+    Unauthenticated,
+}
+
+impl Rejection {
+
+    pub fn status(&self) -> u16 {
+        match self {
+            Rejection::NotFound(_) => 404,
+            Rejection::MethodNotAllowed(_, _) => 405,
+            Rejection::UnsupportedMediaType => 415,
+            Rejection::InvalidJson(_) => 422,
+            Rejection::Unauthenticated => 401,
+            _ => 400,
+        }
+    }
+
+}
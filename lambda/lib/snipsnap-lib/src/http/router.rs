@@ -0,0 +1,556 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use lambda_http::http::Method;
+use lambda_http::request::RequestContext;
+use lambda_http::{Body, Error, Request, RequestExt, Response};
+use percent_encoding::percent_decode_str;
+use tracing::warn;
+
+use crate::http::{
+    ApiError, ApiVersion, ClientVersion, CorsPolicy, ErrorCode, ErrorDetail, FromRequest, Idempotency, MinimumVersions,
+    Rejection, RequestedVersion,
+};
+use crate::http::idempotency::Claim;
+use crate::http::openapi::OpenApiSpec;
+use crate::http::extract::{RouteParameters, request_id};
+use crate::http::versioning::requested_version;
+
+const ORIGIN_HEADER: &str = "origin";
+const PREFLIGHT_METHOD_HEADER: &str = "access-control-request-method";
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, ApiError>> + Send>>;
+type BoxedHandler<S> = Box<dyn Fn(Arc<S>, Request) -> HandlerFuture + Send + Sync>;
+
+/// Dispatches requests to handlers by method and path.
+///
+/// Paths are patterns like `/users/{userId}/logins`, where `{userId}` matches one segment and can be read with the
+/// [`Path`](crate::http::Path) extractor. Every handler receives the shared state `S`, e.g. the
+/// [`Database`](crate::database::Database).
+///
+/// Routes registered directly serve every API version; routes registered with [`Router::versioned`] serve only their
+/// version and take precedence. See [`versioning`](crate::http::versioning) for how a request picks its version.
+pub struct Router<S> {
+    state: Arc<S>,
+    routes: Vec<Route<S>>,
+    versions: Vec<ApiVersion>,
+    error_detail: ErrorDetail,
+    cors: CorsPolicy,
+    minimum_versions: MinimumVersions,
+    spec: Option<OpenApiSpec>,
+    idempotency: Option<Idempotency>,
+}
+
+struct Route<S> {
+    version: Option<u32>,
+    method: Method,
+    path: String,
+    segments: Vec<Segment>,
+    handler: BoxedHandler<S>,
+}
+
+enum Segment {
+    Literal(String),
+    Parameter(String),
+}
+
+impl<S: Send + Sync + 'static> Router<S> {
+
+    /// Error detail, CORS policy and minimum client versions are read from the environment, see
+    /// [`ErrorDetail::from_env`], [`CorsPolicy::from_env`] and [`MinimumVersions::from_env`].
+    pub fn new(state: S) -> Router<S> {
+        Router {
+            state: Arc::new(state),
+            routes: Vec::new(),
+            versions: Vec::new(),
+            error_detail: ErrorDetail::from_env(),
+            cors: CorsPolicy::from_env(),
+            minimum_versions: MinimumVersions::from_env(),
+            spec: None,
+            idempotency: None,
+        }
+    }
+
+    pub fn with_error_detail(mut self, error_detail: ErrorDetail) -> Router<S> {
+        self.error_detail = error_detail;
+        self
+    }
+
+    pub fn with_cors(mut self, cors: CorsPolicy) -> Router<S> {
+        self.cors = cors;
+        self
+    }
+
+    /// Reject app builds older than these with 426 before routing.
+    pub fn with_minimum_versions(mut self, minimum_versions: MinimumVersions) -> Router<S> {
+        self.minimum_versions = minimum_versions;
+        self
+    }
+
+    /// Reject requests that don't match `spec` before they reach the handler, and log responses that don't.
+    pub fn with_spec(mut self, spec: OpenApiSpec) -> Router<S> {
+        self.spec = Some(spec);
+        self
+    }
+
+    /// Honour `Idempotency-Key` on POST, PUT, PATCH and DELETE requests, see [`idempotency`](crate::http::idempotency).
+    pub fn with_idempotency(mut self, idempotency: Idempotency) -> Router<S> {
+        self.idempotency = Some(idempotency);
+        self
+    }
+
+    /// Declare a version, e.g. to mark it deprecated. Versions used with [`Router::versioned`] are declared
+    /// automatically.
+    pub fn with_version(mut self, version: ApiVersion) -> Router<S> {
+        self.versions.retain(|v| v.number() != version.number());
+        self.versions.push(version);
+        self
+    }
+
+    /// Register the routes added in `routes` for `version` only.
+    ///
+    /// ```ignore
+    /// Router::new(database)
+    ///     .with_version(ApiVersion::new(1).deprecated(deprecated).sunset(sunset))
+    ///     .versioned(1, |v| v.post("/login", login_v1))
+    ///     .versioned(2, |v| v.post("/login", login_v2))
+    /// ```
+    pub fn versioned(mut self, version: u32, routes: impl FnOnce(Versioned<S>) -> Versioned<S>) -> Router<S> {
+        if !self.versions.iter().any(|v| v.number() == version) {
+            self.versions.push(ApiVersion::new(version));
+        }
+        routes(Versioned { router: self, version }).router
+    }
+
+    pub fn route<H, F>(self, method: Method, path: &str, handler: H) -> Router<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.add_route(None, method, path, handler)
+    }
+
+    fn add_route<H, F>(mut self, version: Option<u32>, method: Method, path: &str, handler: H) -> Router<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        let segments = split(path)
+            .map(|segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => Segment::Parameter(name.to_string()),
+                None => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        self.routes.push(Route {
+            version,
+            method,
+            path: path.to_string(),
+            segments,
+            handler: Box::new(move |state, request| Box::pin(handler(state, request))),
+        });
+        self
+    }
+
+    pub fn get<H, F>(self, path: &str, handler: H) -> Router<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.route(Method::GET, path, handler)
+    }
+
+    pub fn post<H, F>(self, path: &str, handler: H) -> Router<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.route(Method::POST, path, handler)
+    }
+
+    /// Run the matching handler. [`ApiError`]s, whether from routing or returned by the handler, are logged and become
+    /// error responses tagged with the request id, so the lambda runtime only ever sees a response. `OPTIONS`
+    /// preflights are answered from the [`CorsPolicy`] before anything else, since browsers send them without the
+    /// app's headers, and the policy also adds its headers to every other response.
+    pub async fn handle(&self, request: Request) -> Result<Response<Body>, Error> {
+        let origin = request.headers()
+            .get(ORIGIN_HEADER)
+            .and_then(|o| o.to_str().ok())
+            .map(str::to_string);
+        let mut response = match self.preflight(&request, origin.as_deref()) {
+            Some(response) => response,
+            None => self.dispatch(request).await,
+        };
+        self.cors.apply(origin.as_deref(), &mut response);
+        Ok(response)
+    }
+
+    /// The answer to a preflight for a path with routes in any version.
+    fn preflight(&self, request: &Request, origin: Option<&str>) -> Option<Response<Body>> {
+        if request.method() != Method::OPTIONS || !request.headers().contains_key(PREFLIGHT_METHOD_HEADER) {
+            return None;
+        }
+        let path = match requested_version(request) {
+            Ok((Some(_), path)) if !self.versions.is_empty() => path,
+            _ => route_path(request),
+        };
+        let mut methods: Vec<Method> = Vec::new();
+        for route in self.routes.iter().filter(|route| route.matches(&path).is_some()) {
+            if !methods.contains(&route.method) {
+                methods.push(route.method.clone());
+            }
+        }
+        match methods.is_empty() {
+            true => None,
+            false => Some(self.cors.preflight(origin, &methods)),
+        }
+    }
+
+    async fn dispatch(&self, mut request: Request) -> Response<Body> {
+        let request_id = request_id(&request);
+        let (version, path) = match self.resolve_version(&request) {
+            Ok(resolved) => resolved,
+            Err(e) => return e.with_request_id(request_id).into_response(self.error_detail),
+        };
+
+        let mut allowed = Vec::new();
+        let mut matched = None;
+        for route in self.routes.iter().filter(|route| route.serves(version)) {
+            let Some(parameters) = route.matches(&path) else {
+                continue;
+            };
+            if route.method != request.method() {
+                allowed.push(route.method.clone());
+            } else if matched.is_none() || route.version.is_some() {
+                matched = Some((route, parameters));
+            }
+        }
+
+        let Some((route, parameters)) = matched else {
+            return self.unmatched(request, path, allowed, request_id);
+        };
+        if let Err(e) = self.minimum_versions.check(ClientVersion::from_request(&request).ok().as_ref()) {
+            return e.with_request_id(request_id).into_response(self.error_detail);
+        }
+        if let Some(Err(e)) = self.spec.as_ref().map(|spec| spec.validate_request(&route.path, &request)) {
+            return e.with_request_id(request_id).into_response(self.error_detail);
+        }
+
+        let claim = match &self.idempotency {
+            Some(idempotency) => idempotency.claim(&route.path, &request).await,
+            None => Ok(Claim::Unkeyed),
+        };
+        let claimed = match claim {
+            Ok(Claim::Unkeyed) => None,
+            Ok(Claim::Claimed(claimed)) => Some(claimed),
+            Ok(Claim::Replay(response)) => return self.with_version_headers(response, version),
+            Err(e) => return e.with_request_id(request_id).into_response(self.error_detail),
+        };
+
+        request.extensions_mut().insert(RouteParameters(parameters));
+        if let Some(version) = version {
+            request.extensions_mut().insert(RequestedVersion(version));
+        }
+        let response = match (route.handler)(self.state.clone(), request).await {
+            Ok(response) => response,
+            Err(e) => e.with_request_id(request_id.clone()).into_response(self.error_detail),
+        };
+        if let (Some(idempotency), Some(claimed)) = (&self.idempotency, claimed) {
+            idempotency.complete(&claimed, &response).await;
+        }
+        if let Some(spec) = &self.spec {
+            let errors = spec.validate_response(route.method.as_str(), &route.path, &response);
+            if !errors.is_empty() {
+                warn!(requestId = request_id.unwrap_or_default(), ?errors, "Response does not match the API specification");
+            }
+        }
+        self.with_version_headers(response, version)
+    }
+
+    fn with_version_headers(&self, mut response: Response<Body>, version: Option<u32>) -> Response<Body> {
+        if let Some(version) = self.versions.iter().find(|v| Some(v.number()) == version) {
+            version.apply(&mut response);
+        }
+        response
+    }
+
+    /// The version to serve and the path to route, without any version prefix. Requests that don't ask for a version
+    /// get the oldest one. Routers without versions ignore them entirely.
+    fn resolve_version(&self, request: &Request) -> Result<(Option<u32>, String), ApiError> {
+        if self.versions.is_empty() {
+            return Ok((None, route_path(request)));
+        }
+        let (requested, path) = requested_version(request)?;
+        let oldest = self.versions.iter().map(ApiVersion::number).min();
+        match requested {
+            None => Ok((oldest, path)),
+            Some(requested) if self.versions.iter().any(|v| v.number() == requested) => Ok((Some(requested), path)),
+            Some(requested) => Err(ApiError::new(400, ErrorCode::UnsupportedVersion, format!("Version {requested} is not supported"))),
+        }
+    }
+
+    fn unmatched(&self, request: Request, path: String, allowed: Vec<Method>, request_id: Option<String>) -> Response<Body> {
+        let rejection = if allowed.is_empty() {
+            Rejection::NotFound(path)
+        } else {
+            let allowed = allowed.iter().map(Method::to_string).collect();
+            Rejection::MethodNotAllowed(request.method().to_string(), allowed)
+        };
+        ApiError::from(rejection).with_request_id(request_id).into_response(self.error_detail)
+    }
+}
+
+/// Routes being registered for one version, see [`Router::versioned`].
+pub struct Versioned<S> {
+    router: Router<S>,
+    version: u32,
+}
+
+impl<S: Send + Sync + 'static> Versioned<S> {
+
+    pub fn route<H, F>(mut self, method: Method, path: &str, handler: H) -> Versioned<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.router = self.router.add_route(Some(self.version), method, path, handler);
+        self
+    }
+
+    pub fn get<H, F>(self, path: &str, handler: H) -> Versioned<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.route(Method::GET, path, handler)
+    }
+
+    pub fn post<H, F>(self, path: &str, handler: H) -> Versioned<S>
+        where H: Fn(Arc<S>, Request) -> F + Send + Sync + 'static,
+              F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static
+    {
+        self.route(Method::POST, path, handler)
+    }
+}
+
+impl<S> Route<S> {
+    fn serves(&self, version: Option<u32>) -> bool {
+        self.version.is_none() || self.version == version
+    }
+
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut parameters = HashMap::new();
+        let mut path_segments = split(path);
+        for segment in &self.segments {
+            let path_segment = path_segments.next()?;
+            match segment {
+                Segment::Literal(literal) if literal == path_segment => {}
+                Segment::Literal(_) => return None,
+                Segment::Parameter(name) => {
+                    let value = percent_decode_str(path_segment).decode_utf8().ok()?;
+                    parameters.insert(name.clone(), value.into_owned());
+                }
+            }
+        }
+        match path_segments.next() {
+            Some(_) => None,
+            None => Some(parameters),
+        }
+    }
+}
+
+/// The path as the client sent it, without the `/{stage}` prefix API Gateway adds on stages other than `$default`.
+pub(crate) fn route_path(request: &Request) -> String {
+    let raw_path = request.raw_http_path();
+    let path = match raw_path.is_empty() {
+        true => request.uri().path().to_string(),
+        false => raw_path,
+    };
+    let stage = match request.extensions().get::<RequestContext>() {
+        Some(RequestContext::ApiGatewayV2(context)) => context.stage.as_deref(),
+        Some(RequestContext::ApiGatewayV1(context)) => context.stage.as_deref(),
+        None => None,
+    };
+    match stage.filter(|stage| *stage != "$default") {
+        Some(stage) => match path.strip_prefix(&format!("/{stage}")) {
+            Some("") => String::from("/"),
+            Some(rest) if rest.starts_with('/') => rest.to_string(),
+            _ => path,
+        },
+        None => path,
+    }
+}
+
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use aws_sdk_dynamodb::model::AttributeValue;
+    use chrono::{Duration, TimeZone, Utc};
+    use lambda_http::{Body, Request, Response};
+    use serde::Deserialize;
+
+    use crate::http::{
+        AllowedOrigins, ApiError, ApiVersion, AuthenticatedUser, CorsPolicy, FromRequest, HttpResponseGenerator,
+        MinimumVersions, Path, Router,
+    };
+    use crate::database;
+    use crate::http::client_version::{Build, Platform};
+
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct UserPath {
+        userId: String,
+    }
+
+    async fn user(_state: Arc<()>, request: Request) -> Result<Response<Body>, ApiError> {
+        let Path(path) = Path::<UserPath>::from_request(&request)?;
+        Ok(HttpResponseGenerator::response(200, &path.userId))
+    }
+
+    fn request(method: &str, uri: &str) -> Request {
+        lambda_http::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::Empty)
+            .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let router = Router::new(()).get("/users/{userId}", user);
+
+        let response = router.handle(request("GET", "/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), &Body::from("\"abc\""));
+
+        let response = router.handle(request("POST", "/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers().get("allow").expect("Missing allow header"), "GET");
+
+        let response = router.handle(request("GET", "/users/abc/logins")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 404);
+
+        let response = router.handle(request("GET", "/users/a%20b")).await.expect("Failed to handle request");
+        assert_eq!(response.body(), &Body::from("\"a b\""));
+    }
+
+    async fn stale_write(_state: Arc<()>, _request: Request) -> Result<Response<Body>, ApiError> {
+        let current = HashMap::from([("version".to_string(), AttributeValue::N("4".to_string()))]);
+        Err(database::Error::VersionConflict(Some(current)))?
+    }
+
+    #[tokio::test]
+    async fn test_version_conflict() {
+        let router = Router::new(()).post("/users/{userId}", stale_write);
+
+        let response = router.handle(request("POST", "/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 409);
+        let body: serde_json::Value = serde_json::from_slice(response.body().as_ref()).expect("Body was not JSON");
+        assert_eq!(body["code"], "VERSION_CONFLICT");
+        assert_eq!(body["current"]["version"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_named_stage() {
+        let router = Router::new(()).get("/users/{userId}", user);
+        let request = lambda_http::request::from_str(include_str!("../../tests/named_stage_request.json"))
+            .expect("Failed to parse request");
+        assert_eq!(request.uri().path(), "/dev/users/abc");
+
+        let response = router.handle(request).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), &Body::from("\"abc\""));
+    }
+
+    #[tokio::test]
+    async fn test_rest_api() {
+        let router = Router::new(()).get("/friends", |_state: Arc<()>, request: Request| async move {
+            let user = AuthenticatedUser::from_request(&request)?;
+            Ok(HttpResponseGenerator::response(200, user.user_id()))
+        });
+        let request = lambda_http::request::from_str(include_str!("../../tests/rest_request.json"))
+            .expect("Failed to parse request");
+
+        let response = router.handle(request).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), &Body::from("\"user\""));
+    }
+
+        async fn user_v2(_state: Arc<()>, _request: Request) -> Result<Response<Body>, ApiError> {
+        Ok(HttpResponseGenerator::response(200, "v2"))
+    }
+
+    #[tokio::test]
+    async fn test_versions() {
+        let sunset = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).single().expect("Invalid date");
+        let router = Router::new(())
+            .with_version(ApiVersion::new(1).deprecated(sunset - Duration::days(365 * 10)).sunset(sunset))
+            .versioned(1, |v| v.get("/users/{userId}", user))
+            .versioned(2, |v| v.get("/users/{userId}", user_v2));
+
+        let response = router.handle(request("GET", "/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.body(), &Body::from("\"abc\""));
+        assert!(response.headers().contains_key("deprecation"));
+        assert!(response.headers().contains_key("sunset"));
+
+        let response = router.handle(request("GET", "/v2/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.body(), &Body::from("\"v2\""));
+        assert!(!response.headers().contains_key("deprecation"));
+
+        let response = router.handle(request("GET", "/v3/users/abc")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_minimum_version() {
+        let minimum = MinimumVersions::default().with_minimum(Platform::Ios, Build::parse("150").expect("Invalid build"));
+        let router = Router::new(()).with_minimum_versions(minimum).get("/users/{userId}", user);
+        let request = |client_version| lambda_http::http::Request::builder()
+            .uri("/users/abc")
+            .header("x-client-version", client_version)
+            .body(Body::Empty)
+            .expect("Failed to build request");
+
+        let response = router.handle(request("ios/149")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 426);
+        let response = router.handle(request("ios/150")).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 200);
+
+        // an outdated build still learns that a path doesn't exist
+        let unknown = lambda_http::http::Request::builder()
+            .uri("/users/abc/logins")
+            .header("x-client-version", "ios/149")
+            .body(Body::Empty)
+            .expect("Failed to build request");
+        let response = router.handle(unknown).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let minimum = MinimumVersions::default().with_minimum(Platform::Ios, Build::parse("150").expect("Invalid build"));
+        let router = Router::new(())
+            .with_cors(CorsPolicy::new(AllowedOrigins::List(vec!["https://snipsnap.app".to_string()])))
+            .with_minimum_versions(minimum)
+            .versioned(2, |v| v.get("/users/{userId}", user));
+        let preflight = lambda_http::http::Request::builder()
+            .method("OPTIONS")
+            .uri("/v2/users/abc")
+            .header("origin", "https://snipsnap.app")
+            .header("access-control-request-method", "GET")
+            // an outdated build, which doesn't stop the preflight
+            .header("user-agent", "SnipSnap/149 CFNetwork/1390 Darwin/22.0.0")
+            .body(Body::Empty)
+            .expect("Failed to build request");
+
+        let response = router.handle(preflight).await.expect("Failed to handle request");
+        assert_eq!(response.status(), 204);
+        assert_eq!(response.headers()["access-control-allow-methods"], "GET");
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://snipsnap.app");
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! Session tokens, issued by `/login` once Apple has vouched for the user and sent by the app on every other request.
+//!
+//! A token is the session serialized to json, followed by an HMAC of that json, both base64 encoded. The login handler
+//! and the authorizer must share `SNIPSNAP_SESSION_SECRET`.
+
+use std::env;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::{Rng, thread_rng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SEPARATOR: char = '.';
+const SECRET_VARIABLE: &str = "SNIPSNAP_SESSION_SECRET";
+const DEFAULT_TTL_DAYS: i64 = 30;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    #[error("Malformed session token")]
+    Malformed,
+    #[error("Session token signature does not match")]
+    InvalidSignature,
+    #[error("Session token expired")]
+    Expired,
+}
+
+/// `SNIPSNAP_SESSION_SECRET` isn't set, so tokens couldn't be checked across lambdas.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("{SECRET_VARIABLE} is not set")]
+pub struct MissingSessionSecret;
+
+/// Who a session token was issued to, and until when.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "sub")]
+    user_id: String,
+    #[serde(rename = "dev")]
+    device_id: String,
+    /// Seconds since the epoch.
+    #[serde(rename = "exp")]
+    expires_at: i64,
+}
+
+impl Session {
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.expires_at, 0).single().unwrap_or_default()
+    }
+}
+
+pub struct SessionTokens {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl SessionTokens {
+
+    /// Tokens last 30 days.
+    pub fn new(secret: &[u8]) -> SessionTokens {
+        SessionTokens {
+            secret: secret.to_vec(),
+            ttl: Duration::days(DEFAULT_TTL_DAYS),
+        }
+    }
+
+    /// Tokens with a random secret, only valid within the current process.
+    pub fn ephemeral() -> SessionTokens {
+        SessionTokens::new(&thread_rng().gen::<[u8; 32]>())
+    }
+
+    /// Read `SNIPSNAP_SESSION_SECRET`, which is required, since with a secret of its own the authorizer wouldn't
+    /// accept the tokens the login handler issues.
+    pub fn from_env() -> Result<SessionTokens, MissingSessionSecret> {
+        match env::var(SECRET_VARIABLE).ok().filter(|s| !s.is_empty()) {
+            Some(secret) => Ok(SessionTokens::new(secret.as_bytes())),
+            None => Err(MissingSessionSecret),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> SessionTokens {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A token for the user on the device, and when it expires.
+    pub fn issue(&self, user_id: &str, device_id: &str) -> (String, DateTime<Utc>) {
+        let expires_at = Utc::now() + self.ttl;
+        let session = Session {
+            user_id: user_id.to_string(),
+            device_id: device_id.to_string(),
+            expires_at: expires_at.timestamp(),
+        };
+        let payload = serde_json::to_string(&session).unwrap_or_default();
+        let signature = self.mac(payload.as_bytes()).finalize().into_bytes();
+        let token = format!(
+            "{}{SEPARATOR}{}",
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        );
+        (token, expires_at)
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Session, SessionError> {
+        let (payload, signature) = token.split_once(SEPARATOR).ok_or(SessionError::Malformed)?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).map_err(|_| SessionError::Malformed)?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).map_err(|_| SessionError::Malformed)?;
+        self.mac(&payload).verify_slice(&signature).map_err(|_| SessionError::InvalidSignature)?;
+
+        let session: Session = serde_json::from_slice(&payload).map_err(|_| SessionError::Malformed)?;
+        match session.expires_at > Utc::now().timestamp() {
+            true => Ok(session),
+            false => Err(SessionError::Expired),
+        }
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(payload);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::http::session::{SessionError, SessionTokens};
+
+    #[test]
+    fn test_round_trip() {
+        let tokens = SessionTokens::new(b"secret");
+        let (token, expires_at) = tokens.issue("user", "device");
+        let session = tokens.verify(&token).expect("Failed to verify token");
+        assert_eq!(session.user_id(), "user");
+        assert_eq!(session.device_id(), "device");
+        assert_eq!(session.expires_at().timestamp(), expires_at.timestamp());
+
+        assert_eq!(SessionTokens::new(b"other").verify(&token), Err(SessionError::InvalidSignature));
+        assert_eq!(tokens.verify("not a token"), Err(SessionError::Malformed));
+    }
+
+    #[test]
+    fn test_expired() {
+        let tokens = SessionTokens::new(b"secret").with_ttl(Duration::seconds(-1));
+        let (token, _) = tokens.issue("user", "device");
+        assert_eq!(tokens.verify(&token), Err(SessionError::Expired));
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2022. Josh Bedwell. All rights reserved.
+ */
+
+//! API versions, chosen per request by a `/v{n}` path prefix or the `Accept-Version` header.
+//!
+//! Requests that ask for neither get the oldest version, which is what clients built before versioning expect.
+
+use chrono::{DateTime, Utc};
+use lambda_http::http::header::HeaderValue;
+use lambda_http::{Body, Request, Response};
+
+use crate::http::{ApiError, ErrorCode};
+use crate::http::router::route_path;
+
+pub const ACCEPT_VERSION_HEADER: &str = "accept-version";
+const DEPRECATION_HEADER: &str = "deprecation";
+const SUNSET_HEADER: &str = "sunset";
+
+/// A version of the API and, once it is being phased out, when it was deprecated and when it will stop working.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiVersion {
+    number: u32,
+    deprecated: Option<DateTime<Utc>>,
+    sunset: Option<DateTime<Utc>>,
+}
+
+impl ApiVersion {
+
+    pub fn new(number: u32) -> ApiVersion {
+        ApiVersion {
+            number,
+            deprecated: None,
+            sunset: None,
+        }
+    }
+
+    /// Responses get a `Deprecation` header from `at`.
+    pub fn deprecated(mut self, at: DateTime<Utc>) -> ApiVersion {
+        self.deprecated = Some(at);
+        self
+    }
+    /// Responses get a `Sunset` header announcing when this version goes away.
+    pub fn sunset(mut self, at: DateTime<Utc>) -> ApiVersion {
+        self.sunset = Some(at);
+        self
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Add the `Deprecation` (RFC 9745) and `Sunset` (RFC 8594) headers.
+    pub(crate) fn apply(&self, response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        if let Some(deprecated) = self.deprecated.filter(|d| *d <= Utc::now()) {
+            if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecated.timestamp())) {
+                headers.insert(DEPRECATION_HEADER, value);
+            }
+        }
+        if let Some(sunset) = self.sunset {
+            let http_date = sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            if let Ok(value) = HeaderValue::from_str(&http_date) {
+                headers.insert(SUNSET_HEADER, value);
+            }
+        }
+    }
+}
+
+/// The version a request was routed to, stored in the request extensions for handlers that need it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestedVersion(pub u32);
+
+/// The version a request asks for, if any, and its path without the version prefix.
+pub(crate) fn requested_version(request: &Request) -> Result<(Option<u32>, String), ApiError> {
+    let path = route_path(request);
+    let path = path.as_str();
+    let mut segments = path.trim_start_matches('/').splitn(2, '/');
+    let prefix = segments.next()
+        .and_then(|segment| segment.strip_prefix(['v', 'V']))
+        .and_then(parse_number);
+    if let Some(version) = prefix {
+        return Ok((Some(version), format!("/{}", segments.next().unwrap_or_default())));
+    }
+
+    match request.headers().get(ACCEPT_VERSION_HEADER) {
+        Some(value) => value.to_str()
+            .ok()
+            .and_then(parse_version)
+            .map(|version| (Some(version), path.to_string()))
+            .ok_or_else(|| ApiError::new(400, ErrorCode::UnsupportedVersion, "Invalid Accept-Version header")),
+        None => Ok((None, path.to_string())),
+    }
+}
+
+/// `v1`, `V1` or `1` in `Accept-Version`. Path prefixes need the `v`, so that numeric ids aren't taken for versions.
+fn parse_version(value: &str) -> Option<u32> {
+    let value = value.trim();
+    parse_number(value.strip_prefix(['v', 'V']).unwrap_or(value))
+}
+
+fn parse_number(digits: &str) -> Option<u32> {
+    match digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => None,
+        false => digits.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use lambda_http::{Body, Request, Response};
+
+    use crate::http::versioning::{ApiVersion, requested_version};
+
+    fn request(uri: &str, accept_version: Option<&str>) -> Request {
+        let mut builder = lambda_http::http::Request::builder().uri(uri);
+        if let Some(accept_version) = accept_version {
+            builder = builder.header("accept-version", accept_version);
+        }
+        builder.body(Body::Empty).expect("Failed to build request")
+    }
+
+    #[test]
+    fn test_requested_version() {
+        let version = |uri, header| requested_version(&request(uri, header)).ok();
+        assert_eq!(version("/v2/login", None), Some((Some(2), "/login".to_string())));
+        assert_eq!(version("/login", Some("v3")), Some((Some(3), "/login".to_string())));
+        assert_eq!(version("/login", None), Some((None, "/login".to_string())));
+        assert_eq!(version("/videos/login", None), Some((None, "/videos/login".to_string())));
+        assert_eq!(version("/login", Some("latest")), None);
+        assert_eq!(version("/login", Some("2")), Some((Some(2), "/login".to_string())));
+        assert_eq!(version("/123/logins", None), Some((None, "/123/logins".to_string())));
+        assert_eq!(version("/vv2/logins", None), Some((None, "/vv2/logins".to_string())));
+    }
+
+    #[test]
+    fn test_headers() {
+        let version = ApiVersion::new(1)
+            .deprecated(Utc.with_ymd_and_hms(2022, 6, 30, 0, 0, 0).single().expect("Invalid date"))
+            .sunset(Utc.with_ymd_and_hms(2022, 12, 31, 23, 59, 59).single().expect("Invalid date"));
+        let mut response = Response::new(Body::Empty);
+        version.apply(&mut response);
+        assert_eq!(response.headers()["deprecation"], "@1656547200");
+        assert_eq!(response.headers()["sunset"], "Sat, 31 Dec 2022 23:59:59 GMT");
+    }
+}
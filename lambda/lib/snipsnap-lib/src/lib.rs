@@ -2,10 +2,18 @@
  * Copyright (c) 2022. Josh Bedwell. All rights reserved.
  */
 
+// database::Error carries the sdk errors by value, which are large but only built on failure
+#![allow(clippy::result_large_err)]
+
+pub mod api;
 pub mod database;
 pub mod http;
 
 const REGION: &str = "us-west-2";
+/// The stage a lambda is deployed to, which prefixes table names and picks per-stage defaults.
+const STAGE_VARIABLE: &str = "SNIPSNAP_STAGE";
+/// The stage with developer-friendly defaults, e.g. verbose errors.
+const DEV_STAGE: &str = "dev";
 
 #[cfg(test)]
 mod tests {